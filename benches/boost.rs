@@ -0,0 +1,43 @@
+use advent_of_code_2019::intcode::bigint::BigInt;
+use advent_of_code_2019::intcode::{parse_words, Machine, Word};
+use std::fs::read_to_string;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 20;
+
+/// Runs the BOOST program (day 9) in sensor boost mode, the slowest Intcode
+/// workload in the repository, once per word size, with and without the
+/// peephole optimizer.
+pub fn main() {
+	let text = read_to_string("input/day9/input1.txt").unwrap();
+	for optimize in [false, true] {
+		bench::<i64>("i64", &text, optimize);
+		bench::<i128>("i128", &text, optimize);
+		bench::<BigInt>("BigInt", &text, optimize);
+	}
+}
+
+fn bench<W: Word>(name: &str, text: &str, optimize: bool) {
+	let code: Vec<W> = parse_words(text).ok().unwrap();
+	let mut times: Vec<Duration> = (0..RUNS)
+		.map(|_| {
+			let start = Instant::now();
+			let mut machine = Machine::from_code(code.clone());
+			machine.optimize = optimize;
+			machine.input.push_back(W::from_i64(2));
+			machine.run_until_halt().unwrap();
+			start.elapsed()
+		})
+		.collect();
+	times.sort();
+	let mean = times.iter().sum::<Duration>() / RUNS;
+	println!(
+		"BOOST (day 9, part 2, {}{}): mean {:.1?}, min {:.1?}, max {:.1?} over {} runs",
+		name,
+		if optimize { ", optimized" } else { "" },
+		mean,
+		times[0],
+		times[times.len() - 1],
+		RUNS
+	);
+}
@@ -1,7 +1,7 @@
 use std::fs::read_to_string;
 
 fn fuel(mass: u64) -> u64 {
-	(mass / 3).checked_sub(2).unwrap_or(0)
+	(mass / 3).saturating_sub(2)
 }
 
 fn recursive_fuel(mass: u64) -> u64 {
@@ -1,34 +1,24 @@
+use advent_of_code_2019::intcode::{symbolic, Machine};
 use std::fs::read_to_string;
+use std::ops::Range;
 
-fn calculator(mut code: Vec<u64>) -> Vec<u64> {
-	let mut i = 0;
-	loop {
-		if code[i] == 99 {
-			break;
-		}
-
-		let x1 = code[code[i + 1] as usize];
-		let x2 = code[code[i + 2] as usize];
-		let out = code[i + 3] as usize;
-
-		code[out] = match code[i] {
-			1 => x1 + x2,
-			2 => x1 * x2,
-			_ => unreachable!(),
-		};
-
-		i += 4;
-	}
-	code
+fn calculator(code: Vec<i128>) -> Vec<i128> {
+	let mut machine = Machine::new(code);
+	machine.run_until_halt().unwrap();
+	machine.memory.to_vec()
 }
 
-fn find_noun_verb(code: Vec<u64>, value: u64) -> Option<(u64, u64)> {
-	for noun in 0..100 {
-		for verb in 0..100 {
-			let mut new_code = code.clone();
-			new_code[1] = noun;
-			new_code[2] = verb;
-			if calculator(new_code)[0] == value {
+fn find_noun_verb_brute_force(
+	code: &[i128],
+	value: i128,
+	nouns: Range<i128>,
+	verbs: Range<i128>,
+) -> Option<(i128, i128)> {
+	for noun in nouns {
+		for verb in verbs.clone() {
+			let mut machine = Machine::builder(code.to_vec()).patch(1, noun).patch(2, verb).build();
+			machine.run_until_halt().unwrap();
+			if machine.memory.cell(0) == value {
 				return Some((noun, verb));
 			}
 		}
@@ -36,11 +26,53 @@ fn find_noun_verb(code: Vec<u64>, value: u64) -> Option<(u64, u64)> {
 	None
 }
 
+/// Solves `position 0 == value` on the polynomial the program computes,
+/// one noun at a time. `Err` when the program cannot be run symbolically.
+fn find_noun_verb_symbolic(
+	code: &[i128],
+	value: i128,
+	nouns: Range<i128>,
+	verbs: Range<i128>,
+) -> Result<Option<(i128, i128)>, ()> {
+	let result = symbolic::run(code, &[1, 2])
+		.and_then(|mut memory| memory.swap_remove(0))
+		.ok_or(())?;
+	for noun in nouns {
+		let result = result.substitute(0, noun).ok_or(())?;
+		if result.degree(1) > 1 {
+			for verb in verbs.clone() {
+				if result.substitute(1, verb).and_then(|r| r.as_constant()) == Some(value) {
+					return Ok(Some((noun, verb)));
+				}
+			}
+			continue;
+		}
+		// result = slope * verb + constant
+		let (slope, constant) = (result.coefficient(&[0, 1]), result.coefficient(&[]));
+		let verb = match value.checked_sub(constant) {
+			Some(0) if slope == 0 => Some(verbs.start),
+			Some(difference) if slope != 0 && difference % slope == 0 => Some(difference / slope),
+			_ => None,
+		};
+		if let Some(verb) = verb.filter(|verb| verbs.contains(verb)) {
+			return Ok(Some((noun, verb)));
+		}
+	}
+	Ok(None)
+}
+
+/// First noun, then verb, in the given ranges that leave `value` at
+/// position 0.
+fn find_noun_verb(code: &[i128], value: i128, nouns: Range<i128>, verbs: Range<i128>) -> Option<(i128, i128)> {
+	find_noun_verb_symbolic(code, value, nouns.clone(), verbs.clone())
+		.unwrap_or_else(|_| find_noun_verb_brute_force(code, value, nouns, verbs))
+}
+
 pub fn main() {
 	let input = read_to_string("input/day2/input1.txt").unwrap();
-	let code: Vec<u64> = input.split(',').map(|n| n.parse().unwrap()).collect();
+	let code: Vec<i128> = input.split(',').map(|n| n.parse().unwrap()).collect();
 	println!("PART 1 -> position 0: {}", calculator(code.clone())[0]);
-	let nv = find_noun_verb(code.clone(), 19_690_720).unwrap();
+	let nv = find_noun_verb(&code, 19_690_720, 0..100, 0..100).unwrap();
 	let combined = 100 * nv.0 + nv.1;
 	println!("PART 2 -> noun: {}, verb: {}, combined: {}", nv.0, nv.1, combined);
 }
@@ -72,4 +104,44 @@ mod tests {
 			calculator(vec![1, 1, 1, 4, 99, 5, 6, 0, 99])
 		);
 	}
+
+	#[test]
+	fn day2_test5() {
+		let code: Vec<i128> = read_to_string("input/day2/input1.txt")
+			.unwrap()
+			.split(',')
+			.map(|n| n.trim().parse().unwrap())
+			.collect();
+		let target = 19_690_720;
+		let expected = find_noun_verb_brute_force(&code, target, 0..100, 0..100);
+		assert_eq!(Ok(expected), find_noun_verb_symbolic(&code, target, 0..100, 0..100));
+		assert_eq!(Ok(None), find_noun_verb_symbolic(&code, target, 0..10, 0..10));
+	}
+
+	#[test]
+	fn day2_test6() {
+		// [0] = [1] * [1] + [2], after the usual junk write to [3]
+		let code = vec![1, 0, 0, 3, 2, 1, 1, 3, 1, 3, 2, 0, 99];
+		let expected = find_noun_verb_brute_force(&code, 20, 0..13, 0..13);
+		assert_eq!(Some((3, 11)), expected);
+		assert_eq!(Ok(expected), find_noun_verb_symbolic(&code, 20, 0..13, 0..13));
+
+		// [0] = [2] * [2] * [1]
+		let code = vec![1, 0, 0, 3, 2, 2, 2, 3, 2, 3, 1, 0, 99];
+		let expected = find_noun_verb_brute_force(&code, 12, 0..5, 0..5);
+		assert_eq!(Some((3, 2)), expected);
+		assert_eq!(Ok(expected), find_noun_verb_symbolic(&code, 12, 0..5, 0..5));
+
+		// The noun picks the address of an operand
+		let code = vec![1, 0, 0, 3, 1, 1, 13, 10, 1, 0, 0, 0, 99, 0];
+		let expected = find_noun_verb_brute_force(&code, 3, 0..13, 0..13);
+		assert!(expected.is_some());
+		assert_eq!(Err(()), find_noun_verb_symbolic(&code, 3, 0..13, 0..13));
+		assert_eq!(expected, find_noun_verb(&code, 3, 0..13, 0..13));
+
+		// Writing far out is left to the interpreter
+		let code = vec![1, 0, 0, 268_435_456, 99];
+		assert_eq!(Err(()), find_noun_verb_symbolic(&code, 1, 0..1, 0..1));
+		assert_eq!(Some((0, 0)), find_noun_verb(&code, 1, 0..1, 0..1));
+	}
 }
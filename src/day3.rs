@@ -15,7 +15,7 @@ struct Instruction {
 }
 
 fn manhattan(a: &Point, b: &Point) -> u64 {
-	(a.0 - b.0).abs() as u64 + (a.1 - b.1).abs() as u64
+	(a.0 - b.0).unsigned_abs() + (a.1 - b.1).unsigned_abs()
 }
 
 fn generate_ranges(path: &[Instruction]) -> Vec<Point> {
@@ -55,7 +55,7 @@ fn overlap(p0: &Point, p1: &Point, p2: &Point, p3: &Point) -> Option<Point> {
 	let s = (-s1_y * (p0_x - p2_x) + s1_x * (p0_y - p2_y)) / det;
 	let t = (s2_x * (p0_y - p2_y) - s2_y * (p0_x - p2_x)) / det;
 
-	if s >= 0.0 && s <= 1.0 && t >= 0.0 && t <= 1.0 {
+	if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
 		let ix = (p0_x + (t * s1_x)) as i64;
 		let iy = (p0_y + (t * s1_y)) as i64;
 		Some((ix, iy))
@@ -1,194 +1,24 @@
+use advent_of_code_2019::intcode::Machine;
 use std::fs::read_to_string;
 
 ////////////////////////////////////////
-/// OPERATION
+// CALCULATOR
 ////////////////////////////////////////
 
-#[derive(PartialEq)]
-enum Operation {
-	Add = 1,
-	Mul = 2,
-	Input = 3,
-	Output = 4,
-	JumpIfTrue = 5,
-	JumpIfFalse = 6,
-	LessThan = 7,
-	Equals = 8,
-	Halt = 99,
-}
-
-impl Operation {
-	fn n_parameters(&self) -> usize {
-		match self {
-			Operation::Add => 3,
-			Operation::Mul => 3,
-			Operation::Input => 1,
-			Operation::Output => 1,
-			Operation::Halt => 0,
-			Operation::JumpIfTrue => 2,
-			Operation::JumpIfFalse => 2,
-			Operation::LessThan => 3,
-			Operation::Equals => 3,
-		}
-	}
-}
-
-impl From<i64> for Operation {
-	fn from(n: i64) -> Self {
-		match n {
-			1 => Operation::Add,
-			2 => Operation::Mul,
-			3 => Operation::Input,
-			4 => Operation::Output,
-			5 => Operation::JumpIfTrue,
-			6 => Operation::JumpIfFalse,
-			7 => Operation::LessThan,
-			8 => Operation::Equals,
-			99 => Operation::Halt,
-			_ => unimplemented!(),
-		}
-	}
-}
-
-////////////////////////////////////////
-/// PARAMETER MODE
-////////////////////////////////////////
-
-#[derive(Debug)]
-enum ParameterMode {
-	Position = 0,
-	Immediate = 1,
-}
-
-impl ParameterMode {
-	fn get_parameter(&self, memory: &Vec<i64>, i: usize) -> i64 {
-		match self {
-			ParameterMode::Immediate => memory[i],
-			ParameterMode::Position => memory[memory[i] as usize],
-		}
-	}
-}
-
-impl From<u32> for ParameterMode {
-	fn from(n: u32) -> Self {
-		match n {
-			0 => ParameterMode::Position,
-			1 => ParameterMode::Immediate,
-			_ => unimplemented!(),
-		}
-	}
-}
-
-////////////////////////////////////////
-/// INSTRUCTION
-////////////////////////////////////////
-
-struct Instruction {
-	opcode: Operation,
-	parameter_modes: Vec<ParameterMode>,
-}
-
-impl Instruction {
-	fn parse(opcode: i64) -> Self {
-		let operation = Operation::from(opcode % 100);
-		let par_modes = (0..operation.n_parameters())
-			.map(|i| {
-				let op_str = (opcode / 100).to_string();
-				ParameterMode::from(
-					op_str
-						.as_str()
-						.chars()
-						.rev()
-						.nth(i)
-						.unwrap_or('0')
-						.to_digit(10)
-						.unwrap(),
-				)
-			})
-			.collect();
-		Self {
-			opcode: operation,
-			parameter_modes: par_modes,
-		}
-	}
-
-	fn eval(&self, memory: &mut Vec<i64>, i: &mut usize, input: &mut Vec<i64>, output: &mut Vec<i64>) {
-		let mut new_i = *i + self.opcode.n_parameters() + 1;
-		match self.opcode {
-			Operation::Add => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = x1 + x2;
-			},
-			Operation::Mul => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = x1 * x2;
-			},
-			Operation::Input => {
-				let out = memory[*i + 1] as usize;
-				memory[out] = input.remove(0);
-			},
-			Operation::Output => {
-				let n = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				output.push(n);
-			},
-			Operation::JumpIfTrue => {
-				let n = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				if n != 0 {
-					new_i = self.parameter_modes[1].get_parameter(memory, *i + 2) as usize;
-				}
-			},
-			Operation::JumpIfFalse => {
-				let n = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				if n == 0 {
-					new_i = self.parameter_modes[1].get_parameter(memory, *i + 2) as usize;
-				}
-			},
-			Operation::LessThan => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = if x1 < x2 { 1 } else { 0 };
-			},
-			Operation::Equals => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = if x1 == x2 { 1 } else { 0 };
-			},
-			Operation::Halt => {},
-		}
-		*i = new_i;
-	}
-}
-
-////////////////////////////////////////
-/// CALCULATOR
-////////////////////////////////////////
-
-fn calculator(mut code: Vec<i64>, mut input: Vec<i64>) -> (Vec<i64>, Vec<i64>) {
-	let mut i = 0;
-	let mut output = Vec::new();
-	loop {
-		let ins = Instruction::parse(code[i]);
-		if ins.opcode == Operation::Halt {
-			break;
-		}
-		ins.eval(&mut code, &mut i, &mut input, &mut output);
-	}
-	(code, output)
+fn calculator(code: Vec<i128>, input: Vec<i128>) -> (Vec<i128>, Vec<i128>) {
+	let mut machine = Machine::new(code);
+	machine.input = input.into();
+	machine.run_until_halt().unwrap();
+	(machine.memory.to_vec(), machine.output)
 }
 
 ////////////////////////////////////////
-/// MAIN
+// MAIN
 ////////////////////////////////////////
 
 pub fn main() {
 	let code_str = read_to_string("input/day5/input1.txt").unwrap();
-	let code: Vec<i64> = code_str.split(',').map(|n| n.parse().unwrap()).collect();
+	let code: Vec<i128> = code_str.split(',').map(|n| n.parse().unwrap()).collect();
 
 	let input = vec![1];
 	let (_, output) = calculator(code.clone(), input.clone());
@@ -200,7 +30,7 @@ pub fn main() {
 }
 
 ////////////////////////////////////////
-/// TESTS
+// TESTS
 ////////////////////////////////////////
 
 #[cfg(test)]
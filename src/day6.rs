@@ -10,7 +10,7 @@ use petgraph::Undirected;
 use std::collections::HashMap;
 use std::fs::read_to_string;
 
-fn build_graph<D: EdgeType>(edges: &String) -> GraphMap<&str, (), D> {
+fn build_graph<D: EdgeType>(edges: &str) -> GraphMap<&str, (), D> {
 	GraphMap::<_, (), D>::from_edges(
 		edges
 			.lines()
@@ -45,7 +45,7 @@ fn calculate_path(edges: String) -> usize {
 }
 
 ////////////////////////////////////////
-/// MAIN
+// MAIN
 ////////////////////////////////////////
 
 pub fn main() {
@@ -59,7 +59,7 @@ pub fn main() {
 }
 
 ////////////////////////////////////////
-/// TESTS
+// TESTS
 ////////////////////////////////////////
 
 #[cfg(test)]
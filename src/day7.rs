@@ -1,286 +1,200 @@
-extern crate itertools;
-
-use itertools::Itertools;
+use advent_of_code_2019::intcode::io::Channel;
+use advent_of_code_2019::intcode::search::{search, Objective, Space, Strategy};
+use advent_of_code_2019::intcode::topology::Topology;
+use advent_of_code_2019::intcode::{IntcodeError, Machine};
 use std::fs::read_to_string;
+use std::ops::Range;
+use std::sync::mpsc::channel;
+use std::sync::Mutex;
+use std::thread;
 
 ////////////////////////////////////////
-/// OPERATION
+// TOPOLOGIES
 ////////////////////////////////////////
 
-#[derive(PartialEq)]
-enum Operation {
-	Add = 1,
-	Mul = 2,
-	Input = 3,
-	Output = 4,
-	JumpIfTrue = 5,
-	JumpIfFalse = 6,
-	LessThan = 7,
-	Equals = 8,
-	Halt = 99,
-}
-
-impl Operation {
-	fn n_parameters(&self) -> usize {
-		match self {
-			Operation::Add => 3,
-			Operation::Mul => 3,
-			Operation::Input => 1,
-			Operation::Output => 1,
-			Operation::Halt => 0,
-			Operation::JumpIfTrue => 2,
-			Operation::JumpIfFalse => 2,
-			Operation::LessThan => 3,
-			Operation::Equals => 3,
-		}
-	}
-}
-
-impl From<i64> for Operation {
-	fn from(n: i64) -> Self {
-		match n {
-			1 => Operation::Add,
-			2 => Operation::Mul,
-			3 => Operation::Input,
-			4 => Operation::Output,
-			5 => Operation::JumpIfTrue,
-			6 => Operation::JumpIfFalse,
-			7 => Operation::LessThan,
-			8 => Operation::Equals,
-			99 => Operation::Halt,
-			_ => unimplemented!(),
-		}
-	}
+const SERIES: &str = "
+node a
+node b
+node c
+node d
+node e
+input -> a
+a -> b
+b -> c
+c -> d
+d -> e
+e -> output
+";
+
+const RING: &str = "
+node a
+node b
+node c
+node d
+node e
+input -> a
+a -> b
+b -> c
+c -> d
+d -> e
+e -> a, output
+";
+
+/// Best last output of `topology` over every ordering of `phases`. Of the
+/// orderings that tie, the lowest one is returned.
+fn get_max_signal_of(topology: &str, phases: Range<i128>, code: &[i128]) -> (i128, Vec<i128>) {
+	let topology: Topology = topology.parse().unwrap();
+	let space = Space {
+		alphabet: phases.collect(),
+		length: topology.nodes.len(),
+		repeats: false,
+	};
+	let signal = |phases: &[i128]| {
+		*topology
+			.with_phases(phases)
+			.unwrap()
+			.run(code, &[0])
+			.unwrap()
+			.last()
+			.unwrap()
+	};
+	search(&space, Objective::Max, Strategy::Exhaustive, 1, &signal).remove(0)
 }
 
 ////////////////////////////////////////
-/// PARAMETER MODE
+// PART 1
 ////////////////////////////////////////
 
-#[derive(Debug)]
-enum ParameterMode {
-	Position = 0,
-	Immediate = 1,
-}
-
-impl ParameterMode {
-	fn get_parameter(&self, memory: &Vec<i64>, i: usize) -> i64 {
-		match self {
-			ParameterMode::Immediate => memory[i],
-			ParameterMode::Position => memory[memory[i] as usize],
-		}
-	}
-}
-
-impl From<u32> for ParameterMode {
-	fn from(n: u32) -> Self {
-		match n {
-			0 => ParameterMode::Position,
-			1 => ParameterMode::Immediate,
-			_ => unimplemented!(),
-		}
-	}
+fn get_max_signal(code: &[i128]) -> (i128, Vec<i128>) {
+	get_max_signal_of(SERIES, 0..5, code)
 }
 
 ////////////////////////////////////////
-/// INSTRUCTION
+// PART 2
 ////////////////////////////////////////
 
-struct Instruction {
-	opcode: Operation,
-	parameter_modes: Vec<ParameterMode>,
-}
-
-impl Instruction {
-	fn parse(opcode: i64) -> Self {
-		let operation = Operation::from(opcode % 100);
-		let par_modes = (0..operation.n_parameters())
-			.map(|i| {
-				let op_str = (opcode / 100).to_string();
-				ParameterMode::from(
-					op_str
-						.as_str()
-						.chars()
-						.rev()
-						.nth(i)
-						.unwrap_or('0')
-						.to_digit(10)
-						.unwrap(),
-				)
-			})
-			.collect();
-		Self {
-			opcode: operation,
-			parameter_modes: par_modes,
-		}
-	}
-
-	fn eval(&self, memory: &mut Vec<i64>, i: &mut usize, input: &mut Vec<i64>, output: &mut Vec<i64>) {
-		let mut new_i = *i + self.opcode.n_parameters() + 1;
-		match self.opcode {
-			Operation::Add => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = x1 + x2;
-			},
-			Operation::Mul => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = x1 * x2;
-			},
-			Operation::Input => {
-				let out = memory[*i + 1] as usize;
-				memory[out] = input.remove(0);
-			},
-			Operation::Output => {
-				let n = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				output.push(n);
-			},
-			Operation::JumpIfTrue => {
-				let n = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				if n != 0 {
-					new_i = self.parameter_modes[1].get_parameter(memory, *i + 2) as usize;
-				}
-			},
-			Operation::JumpIfFalse => {
-				let n = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				if n == 0 {
-					new_i = self.parameter_modes[1].get_parameter(memory, *i + 2) as usize;
-				}
-			},
-			Operation::LessThan => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = if x1 < x2 { 1 } else { 0 };
-			},
-			Operation::Equals => {
-				let x1 = self.parameter_modes[0].get_parameter(memory, *i + 1);
-				let x2 = self.parameter_modes[1].get_parameter(memory, *i + 2);
-				let out = memory[*i + 3] as usize;
-				memory[out] = if x1 == x2 { 1 } else { 0 };
-			},
-			Operation::Halt => {},
-		}
-		*i = new_i;
-	}
+fn get_max_signal_with_feedback(code: &[i128]) -> (i128, Vec<i128>) {
+	get_max_signal_of(RING, 5..10, code)
 }
 
 ////////////////////////////////////////
-/// PROCESS
+// THREADS
 ////////////////////////////////////////
 
-#[derive(PartialEq)]
-enum ExitCode {
-	Halt,
-	Wait,
-	Print,
-}
-
-struct Process {
-	ip: usize,
-	code: Vec<i64>,
-	input: Vec<i64>,
-	output: Vec<i64>,
-}
-
-impl Process {
-	fn new(code: Vec<i64>) -> Self {
-		Process {
-			ip: 0,
-			code,
-			input: Vec::new(),
-			output: Vec::new(),
+/// Runs every amplifier on its own thread, each one sending its outputs to
+/// the next through a channel. The last one sends them back here and, with
+/// `feedback`, they are passed on to the first one. Stops once the last
+/// amplifier halts.
+///
+/// An amplifier failing closes its channels, so the ones next to it fail
+/// too; its own error is the one returned.
+fn amplify_threaded(code: &[i128], phases: &[i128], feedback: bool) -> Result<i128, IntcodeError> {
+	let channels: Vec<_> = phases
+		.iter()
+		.map(|phase| {
+			let (sender, receiver) = channel();
+			sender.send(*phase).unwrap();
+			(sender, receiver)
+		})
+		.collect();
+	let (to_main, from_last) = channel();
+	let to_first = channels[0].0.clone();
+	let outputs: Vec<_> = channels
+		.iter()
+		.skip(1)
+		.map(|(sender, _)| sender.clone())
+		.chain(Some(to_main))
+		.collect();
+
+	let amplifiers: Vec<_> = channels
+		.into_iter()
+		.zip(outputs)
+		.map(|((_, input), output)| {
+			let code = code.to_vec();
+			thread::spawn(move || Machine::new(code).run_device(&mut Channel { input, output }))
+		})
+		.collect();
+
+	// The first amplifier may already have failed
+	to_first.send(0).ok();
+	let to_first = Some(to_first).filter(|_| feedback);
+	let mut result = 0;
+	for value in from_last {
+		result = value;
+		if let Some(to_first) = &to_first {
+			// Or halted
+			to_first.send(value).ok();
 		}
 	}
-
-	fn run(&mut self) -> ExitCode {
-		loop {
-			let ins = Instruction::parse(self.code[self.ip]);
-			if ins.opcode == Operation::Halt {
-				return ExitCode::Halt;
-			}
-			if ins.opcode == Operation::Input && self.input.is_empty() {
-				return ExitCode::Wait;
-			}
-			ins.eval(&mut self.code, &mut self.ip, &mut self.input, &mut self.output);
-			if ins.opcode == Operation::Output {
-				return ExitCode::Print;
-			}
-		}
+	drop(to_first);
+
+	let errors: Vec<IntcodeError> = amplifiers
+		.into_iter()
+		.filter_map(|amplifier| amplifier.join().unwrap().err())
+		.collect();
+	match errors
+		.iter()
+		.position(|error| !matches!(error, IntcodeError::Device { .. }))
+	{
+		Some(n) => Err(errors[n].clone()),
+		None => errors.into_iter().next().map_or(Ok(result), Err),
 	}
 }
 
-////////////////////////////////////////
-/// PART 1
-////////////////////////////////////////
-
-fn get_max_signal(code: &Vec<i64>) -> (i64, Vec<i64>) {
-	(0..5)
-		.permutations(5)
-		.map(|phases| {
-			let mut result = 0;
-			for phase in &phases {
-				let mut process = Process::new(code.clone());
-				process.input = vec![*phase, result];
-				process.run();
-				result = process.output[0];
-			}
-			(result, phases)
-		})
-		.max()
-		.unwrap()
-}
-
-////////////////////////////////////////
-/// PART 2
-////////////////////////////////////////
-
-fn get_max_signal_with_feedback(code: &Vec<i64>) -> (i64, Vec<i64>) {
-	(5..10)
-		.permutations(5)
-		.map(|phases| {
-			let mut amplifiers: Vec<_> = phases
-				.iter()
-				.map(|phase| {
-					let mut process = Process::new(code.clone());
-					process.input = vec![*phase];
-					process
-				})
-				.collect();
-
-			let mut result = 0;
-			loop {
-				for process in amplifiers.iter_mut() {
-					process.input.push(result);
-					if process.run() == ExitCode::Halt {
-						return (result, phases);
-					}
-					result = process.output.pop().unwrap();
-				}
-			}
-		})
-		.max()
-		.unwrap()
+/// Like `get_max_signal_of`, with the amplifiers chained in series, or in a
+/// ring with `feedback`, and each running on its own thread.
+fn get_max_signal_threaded(
+	code: &[i128],
+	phases: Range<i128>,
+	feedback: bool,
+) -> Result<(i128, Vec<i128>), IntcodeError> {
+	let alphabet: Vec<i128> = phases.collect();
+	let space = Space {
+		length: alphabet.len(),
+		alphabet,
+		repeats: false,
+	};
+	let failure = Mutex::new(None);
+	let signal = |phases: &[i128]| match amplify_threaded(code, phases, feedback) {
+		Ok(signal) => signal,
+		Err(error) => {
+			failure.lock().unwrap().get_or_insert(error);
+			i128::MIN
+		},
+	};
+	let best = search(&space, Objective::Max, Strategy::Exhaustive, 1, &signal).remove(0);
+	match failure.into_inner().unwrap() {
+		Some(error) => Err(error),
+		None => Ok(best),
+	}
 }
 
 ////////////////////////////////////////
-/// MAIN
+// MAIN
 ////////////////////////////////////////
 
 pub fn main() {
 	let code_str = read_to_string("input/day7/input1.txt").unwrap();
-	let code: Vec<i64> = code_str.split(',').map(|n| n.parse().unwrap()).collect();
-	let (signal, phases) = get_max_signal(&code);
+	let code: Vec<i128> = code_str.split(',').map(|n| n.parse().unwrap()).collect();
+	let threaded = std::env::args().any(|arg| arg == "--threads");
+	let (signal, phases) = if threaded {
+		get_max_signal_threaded(&code, 0..5, false).unwrap()
+	}
+	else {
+		get_max_signal(&code)
+	};
 	println!("PART 1 -> Max thruster signal {} (phase: {:?})", signal, phases);
-	let (signal, phases) = get_max_signal_with_feedback(&code);
+	let (signal, phases) = if threaded {
+		get_max_signal_threaded(&code, 5..10, true).unwrap()
+	}
+	else {
+		get_max_signal_with_feedback(&code)
+	};
 	println!("PART 2 -> Max thruster signal {} (phase: {:?})", signal, phases);
 }
 
 ////////////////////////////////////////
-/// TESTS
+// TESTS
 ////////////////////////////////////////
 
 #[cfg(test)]
@@ -290,7 +204,7 @@ mod tests {
 
 	#[test]
 	fn day7_test1() {
-		let code: Vec<i64> = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+		let code: Vec<i128> = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
 		let res = get_max_signal(&code);
 		assert_eq!(43210, res.0);
 		assert_eq!(vec![4, 3, 2, 1, 0], res.1);
@@ -298,7 +212,7 @@ mod tests {
 
 	#[test]
 	fn day7_test2() {
-		let code: Vec<i64> = vec![
+		let code: Vec<i128> = vec![
 			3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0,
 		];
 		let res = get_max_signal(&code);
@@ -308,7 +222,7 @@ mod tests {
 
 	#[test]
 	fn day7_test3() {
-		let code: Vec<i64> = vec![
+		let code: Vec<i128> = vec![
 			3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1, 33, 31, 31, 1, 32,
 			31, 31, 4, 31, 99, 0, 0, 0,
 		];
@@ -319,7 +233,7 @@ mod tests {
 
 	#[test]
 	fn day7_test4() {
-		let code: Vec<i64> = vec![
+		let code: Vec<i128> = vec![
 			3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99,
 			0, 0, 5,
 		];
@@ -330,7 +244,7 @@ mod tests {
 
 	#[test]
 	fn day7_test5() {
-		let code: Vec<i64> = vec![
+		let code: Vec<i128> = vec![
 			3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54, -5, 54, 1105, 1,
 			12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4, 53, 1001, 56, -1, 56, 1005, 56, 6,
 			99, 0, 0, 0, 0, 10,
@@ -339,4 +253,53 @@ mod tests {
 		assert_eq!(18216, res.0);
 		assert_eq!(vec![9, 7, 8, 5, 6], res.1);
 	}
+
+	#[test]
+	fn day7_test6() {
+		let programs: Vec<Vec<i128>> = vec![
+			vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0],
+			vec![
+				3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0,
+			],
+		];
+		for code in programs {
+			assert_eq!(Ok(get_max_signal(&code)), get_max_signal_threaded(&code, 0..5, false));
+		}
+	}
+
+	#[test]
+	fn day7_test7() {
+		let programs: Vec<Vec<i128>> = vec![
+			vec![
+				3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6,
+				99, 0, 0, 5,
+			],
+			vec![
+				3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54, -5, 54, 1105,
+				1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4, 53, 1001, 56, -1, 56, 1005,
+				56, 6, 99, 0, 0, 0, 0, 10,
+			],
+		];
+		for code in programs {
+			assert_eq!(
+				Ok(get_max_signal_with_feedback(&code)),
+				get_max_signal_threaded(&code, 5..10, true)
+			);
+		}
+	}
+
+	#[test]
+	fn day7_test8() {
+		// Faults after reading its phase, taking its neighbours down with it
+		let faulty = vec![3, 10, 98];
+		for feedback in [false, true] {
+			let error = amplify_threaded(&faulty, &[0, 1, 2], feedback).unwrap_err();
+			assert!(matches!(error, IntcodeError::UnknownOpcode { ip: 2, .. }));
+		}
+		assert!(get_max_signal_threaded(&faulty, 0..5, false).is_err());
+
+		// Fewer amplifiers
+		let code = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+		assert_eq!(Ok((210, vec![2, 1, 0])), get_max_signal_threaded(&code, 0..3, false));
+	}
 }
@@ -1,7 +1,7 @@
 use std::fs::read_to_string;
 
 ////////////////////////////////////////
-/// PART 1
+// PART 1
 ////////////////////////////////////////
 
 fn layers(image: String, n_pixels: usize) -> Vec<[usize; 3]> {
@@ -29,7 +29,7 @@ fn count(layers: Vec<[usize; 3]>) -> usize {
 }
 
 ////////////////////////////////////////
-/// PART 2
+// PART 2
 ////////////////////////////////////////
 
 fn render(image: String, n_pixels: usize) -> Vec<char> {
@@ -56,7 +56,7 @@ fn render(image: String, n_pixels: usize) -> Vec<char> {
 }
 
 ////////////////////////////////////////
-/// MAIN
+// MAIN
 ////////////////////////////////////////
 
 pub fn main() {
@@ -71,7 +71,7 @@ pub fn main() {
 	let rendered = render(input, WIDTH * HEIGHT);
 	for (i, pixel) in rendered.iter().enumerate() {
 		if i % WIDTH == WIDTH - 1 {
-			print!("{}\n", pixel);
+			println!("{}", pixel);
 		}
 		else {
 			print!("{}", pixel);
@@ -80,7 +80,7 @@ pub fn main() {
 }
 
 ////////////////////////////////////////
-/// TESTS
+// TESTS
 ////////////////////////////////////////
 
 #[cfg(test)]
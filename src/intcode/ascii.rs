@@ -0,0 +1,299 @@
+//! Programs that talk in text: outputs below 128 are characters, anything
+//! else a number, and every line sent becomes its character codes followed
+//! by a newline (10). Also the session behind the `intcode-play` binary.
+
+use super::transcript::Event;
+use super::{ExitCode, IntcodeError, Machine};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+const HELP: &str = "\
+:history     list the lines sent so far
+!!           send the last line again
+!<n>         send line n of the history again
+:help        print this help
+:quit        leave
+Anything else is sent to the program.";
+
+////////////////////////////////////////
+// TEXT
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NotAsciiError(pub String);
+
+impl fmt::Display for NotAsciiError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "only ASCII text can be sent, not `{}`", self.0)
+	}
+}
+
+impl Error for NotAsciiError {}
+
+/// The inputs for one line of text, without its line ending.
+pub fn encode(line: &str) -> Result<Vec<i128>, NotAsciiError> {
+	let line = line.trim_end_matches(['\n', '\r']);
+	if !line.is_ascii() {
+		return Err(NotAsciiError(line.to_string()));
+	}
+	Ok(line.bytes().map(i128::from).chain(std::iter::once(10)).collect())
+}
+
+/// What a program printed between two inputs.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Screen {
+	pub text: String,
+	/// Outputs that are not characters, usually the answer.
+	pub numbers: Vec<i128>,
+	pub halted: bool,
+}
+
+impl Screen {
+	pub fn decode(outputs: &[i128], halted: bool) -> Self {
+		let mut screen = Screen {
+			halted,
+			..Screen::default()
+		};
+		for value in outputs {
+			match value {
+				0..=127 => screen.text.push(*value as u8 as char),
+				_ => screen.numbers.push(*value),
+			}
+		}
+		screen
+	}
+}
+
+/// The text, then each number on a line of its own.
+impl fmt::Display for Screen {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.text)?;
+		if !self.text.is_empty() && !self.text.ends_with('\n') && !self.numbers.is_empty() {
+			writeln!(f)?;
+		}
+		for n in &self.numbers {
+			writeln!(f, "{}", n)?;
+		}
+		Ok(())
+	}
+}
+
+////////////////////////////////////////
+// ADAPTER
+////////////////////////////////////////
+
+pub struct AsciiMachine {
+	pub machine: Machine,
+	/// What the program read and printed, when it is being recorded.
+	pub transcript: Option<Vec<Event>>,
+}
+
+impl AsciiMachine {
+	pub fn new(machine: Machine) -> Self {
+		Self {
+			machine,
+			transcript: None,
+		}
+	}
+
+	pub fn send(&mut self, line: &str) -> Result<(), NotAsciiError> {
+		self.machine.input.extend(encode(line)?);
+		Ok(())
+	}
+
+	/// Runs until the program halts or waits for another line.
+	pub fn run(&mut self) -> Result<Screen, IntcodeError> {
+		loop {
+			let exit = match &mut self.transcript {
+				Some(events) => self.machine.run_transcribed(events)?,
+				None => self.machine.run()?,
+			};
+			match exit {
+				ExitCode::Print => {},
+				exit => {
+					let outputs: Vec<i128> = self.machine.output.drain(..).collect();
+					return Ok(Screen::decode(&outputs, exit == ExitCode::Halt));
+				},
+			}
+		}
+	}
+}
+
+////////////////////////////////////////
+// PLAYER
+////////////////////////////////////////
+
+/// An interactive session with a text program. Everything shown and every
+/// line sent is copied to `transcript`.
+pub struct Player<L: Write> {
+	pub ascii: AsciiMachine,
+	/// Lines sent to the program, oldest first.
+	pub history: Vec<String>,
+	transcript: L,
+	stopped: bool,
+}
+
+impl<L: Write> Player<L> {
+	pub fn new(machine: Machine, transcript: L) -> Self {
+		Self {
+			ascii: AsciiMachine::new(machine),
+			history: Vec::new(),
+			transcript,
+			stopped: false,
+		}
+	}
+
+	/// Whether the program halted or failed, so nothing more can be sent.
+	pub fn stopped(&self) -> bool {
+		self.stopped
+	}
+
+	/// Runs the program up to its first question.
+	pub fn start(&mut self) -> io::Result<String> {
+		self.resume()
+	}
+
+	fn resume(&mut self) -> io::Result<String> {
+		let (screen, note) = match self.ascii.run() {
+			Ok(screen) if screen.halted => (screen, Some(String::from("[halted]"))),
+			Ok(screen) => (screen, None),
+			Err(error) => {
+				let outputs: Vec<i128> = self.ascii.machine.output.drain(..).collect();
+				(Screen::decode(&outputs, false), Some(format!("error: {}", error)))
+			},
+		};
+		self.stopped = note.is_some();
+		let mut shown = screen.to_string();
+		if let Some(note) = note {
+			if !shown.is_empty() && !shown.ends_with('\n') {
+				shown.push('\n');
+			}
+			shown += &format!("{}\n", note);
+		}
+		self.transcript.write_all(shown.as_bytes())?;
+		self.transcript.flush()?;
+		Ok(shown)
+	}
+
+	/// The line `!!` or `!<n>` stands for.
+	fn recall(&self, line: &str) -> Result<String, String> {
+		let n = match &line[1..] {
+			"!" => self.history.len(),
+			n => n.parse().map_err(|_| format!("invalid history entry `{}`", n))?,
+		};
+		match n.checked_sub(1).and_then(|i| self.history.get(i)) {
+			Some(line) => Ok(line.clone()),
+			None => Err(format!("no line {} in the history", n)),
+		}
+	}
+
+	/// Handles a line typed by the user. Returns what to show, or `None` to
+	/// leave.
+	pub fn execute(&mut self, line: &str) -> io::Result<Option<String>> {
+		let line = line.trim_end_matches(['\n', '\r']);
+		let line = match line {
+			":quit" => return Ok(None),
+			":help" => return Ok(Some(format!("{}\n", HELP))),
+			":history" => {
+				let lines = self.history.iter().enumerate();
+				return Ok(Some(
+					lines.map(|(i, line)| format!("{:>4}  {}\n", i + 1, line)).collect(),
+				));
+			},
+			_ if line.starts_with('!') && line.len() > 1 => match self.recall(line) {
+				Ok(line) => line,
+				Err(message) => return Ok(Some(format!("{}\n", message))),
+			},
+			_ => line.to_string(),
+		};
+		if self.stopped {
+			return Ok(Some(String::from("the program has stopped\n")));
+		}
+		if let Err(error) = self.ascii.send(&line) {
+			return Ok(Some(format!("{}\n", error)));
+		}
+		writeln!(self.transcript, "> {}", line)?;
+		self.history.push(line);
+		self.resume().map(Some)
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	/// Asks with `?`, then echoes each line followed by 1000. A line
+	/// with a `q` in it makes it halt.
+	fn echo() -> Vec<i128> {
+		vec![
+			104, 63, 104, 10, 3, 100, 1008, 100, 113, 101, 1005, 101, 30, 1008, 100, 10, 101, 1005, 101, 25, 4, 100,
+			1105, 1, 4, 104, 1000, 1105, 1, 0, 99,
+		]
+	}
+
+	#[test]
+	fn ascii_test1() {
+		assert_eq!(Ok(vec![72, 105, 10]), encode("Hi\r\n"));
+		assert_eq!(Ok(vec![10]), encode(""));
+		assert_eq!(Err(NotAsciiError(String::from("né"))), encode("né"));
+
+		let screen = Screen::decode(&[72, 105, 1000, 10, -1], true);
+		assert_eq!("Hi\n", screen.text);
+		assert_eq!(vec![1000, -1], screen.numbers);
+		assert_eq!("Hi\n1000\n-1\n", screen.to_string());
+		assert_eq!("Hi\n1000\n", Screen::decode(&[72, 105, 1000], false).to_string());
+	}
+
+	#[test]
+	fn ascii_test2() {
+		let mut ascii = AsciiMachine::new(Machine::new(echo()));
+		assert_eq!(Ok(Screen::decode(&[63, 10], false)), ascii.run());
+		ascii.send("hi").unwrap();
+		let screen = ascii.run().unwrap();
+		assert_eq!("hi?\n", screen.text);
+		assert_eq!(vec![1000], screen.numbers);
+		assert!(!screen.halted);
+		assert!(ascii.send("ü").is_err());
+		ascii.send("q").unwrap();
+		assert!(ascii.run().unwrap().halted);
+	}
+
+	#[test]
+	fn ascii_test3() {
+		let mut player = Player::new(Machine::new(echo()), Vec::new());
+		assert_eq!("?\n", player.start().unwrap());
+		assert_eq!(Some(String::from("ab?\n1000\n")), player.execute("ab\n").unwrap());
+		assert_eq!(Some(String::from("cd?\n1000\n")), player.execute("cd").unwrap());
+		assert_eq!(Some(String::from("ab?\n1000\n")), player.execute("!1").unwrap());
+		assert_eq!(Some(String::from("ab?\n1000\n")), player.execute("!!").unwrap());
+		assert_eq!(
+			Some(String::from("no line 7 in the history\n")),
+			player.execute("!7").unwrap()
+		);
+		assert_eq!(
+			Some(String::from("   1  ab\n   2  cd\n   3  ab\n   4  ab\n")),
+			player.execute(":history").unwrap()
+		);
+		assert_eq!(Some(String::from("[halted]\n")), player.execute("q").unwrap());
+		assert!(player.stopped());
+		assert_eq!(
+			Some(String::from("the program has stopped\n")),
+			player.execute("ab").unwrap()
+		);
+		assert_eq!(None, player.execute(":quit").unwrap());
+		assert_eq!(
+			"?\n> ab\nab?\n1000\n> cd\ncd?\n1000\n> ab\nab?\n1000\n> ab\nab?\n1000\n> q\n[halted]\n",
+			String::from_utf8(player.transcript).unwrap()
+		);
+
+		let mut player = Player::new(Machine::new(vec![104, 72, 98]), Vec::new());
+		assert!(player.start().unwrap().starts_with("H\nerror: unknown opcode 98 at 2"));
+		assert!(player.stopped());
+	}
+}
@@ -0,0 +1,386 @@
+//! A small assembly language for Intcode.
+//!
+//! ```text
+//! ; comments start with a semicolon
+//! start:  in [rb+1]            ; relative operand
+//!         add [rb+1], #5, [100] ; immediate and position operands
+//!         jt #1, #start         ; labels are plain addresses
+//! value:  .data 1, -2, value+1  ; raw words
+//! ```
+
+use super::{Operation, ParameterMode};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+////////////////////////////////////////
+// ERROR
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssembleError {
+	/// 1-based line number in the source.
+	pub line: usize,
+	pub text: String,
+	pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: {}\n    {}", self.line, self.message, self.text.trim())
+	}
+}
+
+impl Error for AssembleError {}
+
+////////////////////////////////////////
+// SYNTAX
+////////////////////////////////////////
+
+/// A number plus or minus any amount of labels, e.g. `loop+2` or `end-start`.
+struct Expr {
+	/// As written, for error messages.
+	text: String,
+	offset: i128,
+	labels: Vec<(i128, String)>,
+}
+
+impl Expr {
+	fn too_large(&self) -> String {
+		format!("value `{}` does not fit in a word", self.text)
+	}
+}
+
+struct Operand {
+	mode: ParameterMode,
+	value: Expr,
+}
+
+enum Statement {
+	Instruction(Operation, Vec<Operand>),
+	Data(Vec<Expr>),
+}
+
+impl Statement {
+	fn len(&self) -> usize {
+		match self {
+			Statement::Instruction(operation, _) => operation.n_parameters() + 1,
+			Statement::Data(words) => words.len(),
+		}
+	}
+}
+
+fn is_identifier(text: &str) -> bool {
+	let mut chars = text.chars();
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+		_ => false,
+	}
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+	let invalid = || format!("invalid value `{}`", text.trim());
+	let mut expr = Expr {
+		text: text.trim().to_string(),
+		offset: 0,
+		labels: Vec::new(),
+	};
+	let mut sign = 1;
+	let mut term = String::new();
+	let mut chars = text.chars();
+	loop {
+		let c = chars.next();
+		if let Some(c) = c.filter(|c| *c != '+' && *c != '-') {
+			term.push(c);
+			continue;
+		}
+
+		let current = std::mem::take(&mut term);
+		let current = current.trim();
+		if current.is_empty() {
+			match c {
+				Some('-') => sign = -sign,
+				Some(_) => {},
+				None => return Err(invalid()),
+			}
+			continue;
+		}
+		if let Ok(n) = current.parse::<u128>() {
+			let offset = match sign {
+				1 => i128::try_from(n).ok().and_then(|n| expr.offset.checked_add(n)),
+				_ => expr.offset.checked_sub_unsigned(n),
+			};
+			expr.offset = offset.ok_or_else(|| expr.too_large())?;
+		}
+		else if is_identifier(current) && current != "rb" {
+			expr.labels.push((sign, current.to_string()));
+		}
+		else {
+			return Err(invalid());
+		}
+
+		match c {
+			Some('-') => sign = -1,
+			Some(_) => sign = 1,
+			None => return Ok(expr),
+		}
+	}
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+	let text = text.trim();
+	if let Some(value) = text.strip_prefix('#') {
+		return Ok(Operand {
+			mode: ParameterMode::Immediate,
+			value: parse_expr(value)?,
+		});
+	}
+	if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+		let inner = inner.trim();
+		if let Some(offset) = inner.strip_prefix("rb") {
+			let offset = offset.trim();
+			if offset.is_empty() || offset.starts_with('+') || offset.starts_with('-') {
+				return Ok(Operand {
+					mode: ParameterMode::Relative,
+					value: match offset {
+						"" => parse_expr("0")?,
+						offset => parse_expr(offset)?,
+					},
+				});
+			}
+		}
+		return Ok(Operand {
+			mode: ParameterMode::Position,
+			value: parse_expr(inner)?,
+		});
+	}
+	Err(format!(
+		"invalid operand `{}` (expected `#value`, `[address]` or `[rb+offset]`)",
+		text
+	))
+}
+
+fn parse_statement(text: &str) -> Result<Option<Statement>, String> {
+	let text = text.trim();
+	if text.is_empty() {
+		return Ok(None);
+	}
+
+	let (head, rest) = match text.find(char::is_whitespace) {
+		Some(i) => (&text[..i], text[i..].trim()),
+		None => (text, ""),
+	};
+	let arguments: Vec<&str> = if rest.is_empty() {
+		Vec::new()
+	}
+	else {
+		rest.split(',').collect()
+	};
+
+	if head == ".data" {
+		if arguments.is_empty() {
+			return Err(String::from("`.data` needs at least one value"));
+		}
+		let words = arguments.into_iter().map(parse_expr).collect::<Result<_, _>>()?;
+		return Ok(Some(Statement::Data(words)));
+	}
+
+	let operation = Operation::from_mnemonic(&head.to_lowercase()).ok_or(format!("unknown mnemonic `{}`", head))?;
+	if arguments.len() != operation.n_parameters() {
+		return Err(format!(
+			"`{}` takes {} operand(s), found {}",
+			operation.mnemonic(),
+			operation.n_parameters(),
+			arguments.len()
+		));
+	}
+	let operands: Vec<Operand> = arguments.into_iter().map(parse_operand).collect::<Result<_, _>>()?;
+	if let Some(n) = operation.output_parameter() {
+		if operands[n].mode == ParameterMode::Immediate {
+			return Err(format!(
+				"`{}` cannot write to an immediate operand",
+				operation.mnemonic()
+			));
+		}
+	}
+	Ok(Some(Statement::Instruction(operation, operands)))
+}
+
+////////////////////////////////////////
+// ASSEMBLER
+////////////////////////////////////////
+
+fn resolve(expr: &Expr, labels: &HashMap<String, i128>) -> Result<i128, String> {
+	let mut value = expr.offset;
+	for (sign, label) in &expr.labels {
+		match labels.get(label) {
+			Some(address) => value = value.checked_add(sign * address).ok_or_else(|| expr.too_large())?,
+			None => return Err(format!("undefined label `{}`", label)),
+		}
+	}
+	Ok(value)
+}
+
+/// Turns assembly source into a program ready for `Machine::new`.
+pub fn assemble(source: &str) -> Result<Vec<i128>, AssembleError> {
+	let mut labels = HashMap::new();
+	let mut statements = Vec::new();
+	let mut address = 0;
+
+	for (n, text) in source.lines().enumerate() {
+		let error = |message| AssembleError {
+			line: n + 1,
+			text: text.to_string(),
+			message,
+		};
+
+		let mut code = text.split(';').next().unwrap();
+		while let Some(i) = code.find(':') {
+			let label = code[..i].trim();
+			if !is_identifier(label) || label == "rb" {
+				return Err(error(format!("invalid label `{}`", label)));
+			}
+			if labels.insert(label.to_string(), address as i128).is_some() {
+				return Err(error(format!("label `{}` defined twice", label)));
+			}
+			code = &code[i + 1..];
+		}
+
+		if let Some(statement) = parse_statement(code).map_err(error)? {
+			address += statement.len();
+			statements.push((n, statement));
+		}
+	}
+
+	let lines: Vec<&str> = source.lines().collect();
+	let mut program = Vec::with_capacity(address);
+	for (n, statement) in statements {
+		let error = |message| AssembleError {
+			line: n + 1,
+			text: lines[n].to_string(),
+			message,
+		};
+		match statement {
+			Statement::Instruction(operation, operands) => {
+				let modes = operands
+					.iter()
+					.rev()
+					.fold(0, |modes, operand| modes * 10 + operand.mode as i128);
+				program.push(modes * 100 + operation as i128);
+				for operand in operands {
+					program.push(resolve(&operand.value, &labels).map_err(error)?);
+				}
+			},
+			Statement::Data(words) => {
+				for word in words {
+					program.push(resolve(&word, &labels).map_err(error)?);
+				}
+			},
+		}
+	}
+	Ok(program)
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn assembler_test1() {
+		let source = "
+			; prints a copy of itself
+			start:  arb #1
+			        out [rb-1]
+			        add [100], #1, [100]
+			        eq [100], #16, [101]
+			        jf [101], #start
+			        hlt
+		";
+		assert_eq!(
+			vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99],
+			assemble(source).unwrap()
+		);
+	}
+
+	#[test]
+	fn assembler_test2() {
+		let source = "
+			        in [input]
+			        eq [input], #8, [flag]
+			        jt [flag], #equal
+			        lt #8, [input], [flag]
+			        jf [flag], #below
+			        jf #0, #above
+			        .data 98
+			flag:   .data 0
+			input:  .data 0
+			equal:  mul [input], #125, [flag]
+			        out [flag]
+			        jt #1, #end
+			below:  out #999
+			        jt #1, #end
+			above:  add #1000, #1, [flag]
+			        out [flag]
+			        jt #1, #end
+			        .data end-above+88  ; 98
+			end:    hlt
+		";
+		let code = vec![
+			3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125,
+			20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+		];
+		assert_eq!(code, assemble(source).unwrap());
+	}
+
+	#[test]
+	fn assembler_test3() {
+		let error = assemble("hlt\n  jmp #3\n").unwrap_err();
+		assert_eq!(2, error.line);
+		assert_eq!("unknown mnemonic `jmp`", error.message);
+		assert_eq!("line 2: unknown mnemonic `jmp`\n    jmp #3", error.to_string());
+	}
+
+	#[test]
+	fn assembler_test4() {
+		assert_eq!(3, assemble("hlt\n\nout [nowhere]").unwrap_err().line);
+		assert_eq!(1, assemble("add #1, #2, #3").unwrap_err().line);
+		assert_eq!(2, assemble("a: hlt\na: hlt").unwrap_err().line);
+		assert_eq!(1, assemble("out #1, #2").unwrap_err().line);
+		assert_eq!(1, assemble("out 5").unwrap_err().line);
+	}
+
+	#[test]
+	fn assembler_test5() {
+		let max = i128::MAX.to_string();
+		let min = i128::MIN.to_string();
+		assert_eq!(
+			Ok(vec![i128::MAX, i128::MIN]),
+			assemble(&format!(".data {}, {}", max, min))
+		);
+		assert_eq!(Ok(vec![i128::MIN]), assemble(&format!(".data -{}-1", max)));
+
+		let error = assemble(&format!(
+			"hlt
+.data {}+1",
+			max
+		))
+		.unwrap_err();
+		assert_eq!(2, error.line);
+		assert_eq!(format!("value `{}+1` does not fit in a word", max), error.message);
+		let error = assemble(&format!("hlt\nend: .data {}+end", max)).unwrap_err();
+		assert_eq!(format!("value `{}+end` does not fit in a word", max), error.message);
+		let error = assemble(&format!("out [rb{}-1]", min)).unwrap_err();
+		assert_eq!(format!("value `{}-1` does not fit in a word", min), error.message);
+		assert_eq!(
+			format!("line 1: invalid value `-{}x`\n    out [rb-{}x]", max, max),
+			assemble(&format!("out [rb-{}x]", max)).unwrap_err().to_string()
+		);
+		assert_eq!(Ok(vec![204, -5, 204, 0]), assemble("out [rb-5]\nout [rb]"));
+	}
+}
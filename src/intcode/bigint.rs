@@ -0,0 +1,303 @@
+//! Arbitrary precision integers, just enough of them to run Intcode.
+
+use super::word::Word;
+use super::MAX_ADDRESS;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Sign and magnitude, the magnitude in base 2^32 with the least
+/// significant limb first. There are no leading zero limbs, and zero is
+/// never negative.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct BigInt {
+	negative: bool,
+	limbs: Vec<u32>,
+}
+
+////////////////////////////////////////
+// MAGNITUDES
+////////////////////////////////////////
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+	a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+	let mut carry = 0u64;
+	for i in 0..a.len().max(b.len()) {
+		let s = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+		sum.push(s as u32);
+		carry = s >> 32;
+	}
+	if carry > 0 {
+		sum.push(carry as u32);
+	}
+	sum
+}
+
+/// `a - b`, with `a >= b`.
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut difference = Vec::with_capacity(a.len());
+	let mut borrow = 0i64;
+	for (i, x) in a.iter().enumerate() {
+		let mut d = *x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+		borrow = 0;
+		if d < 0 {
+			d += 1 << 32;
+			borrow = 1;
+		}
+		difference.push(d as u32);
+	}
+	difference
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut product = vec![0u32; a.len() + b.len()];
+	for (i, x) in a.iter().enumerate() {
+		let mut carry = 0u64;
+		for (j, y) in b.iter().enumerate() {
+			let p = *x as u64 * *y as u64 + product[i + j] as u64 + carry;
+			product[i + j] = p as u32;
+			carry = p >> 32;
+		}
+		product[i + b.len()] = carry as u32;
+	}
+	product
+}
+
+/// Divides in place by a small divisor, returning the remainder.
+fn div_small(limbs: &mut Vec<u32>, divisor: u32) -> u32 {
+	let mut remainder = 0u64;
+	for limb in limbs.iter_mut().rev() {
+		let n = (remainder << 32) | *limb as u64;
+		*limb = (n / divisor as u64) as u32;
+		remainder = n % divisor as u64;
+	}
+	trim(limbs);
+	remainder as u32
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+	while limbs.last() == Some(&0) {
+		limbs.pop();
+	}
+}
+
+////////////////////////////////////////
+// BIGINT
+////////////////////////////////////////
+
+impl BigInt {
+	fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+		trim(&mut limbs);
+		Self {
+			negative: negative && !limbs.is_empty(),
+			limbs,
+		}
+	}
+
+	fn magnitude(&self) -> Option<u128> {
+		if self.limbs.len() > 4 {
+			return None;
+		}
+		Some(self.limbs.iter().rev().fold(0u128, |n, limb| (n << 32) | *limb as u128))
+	}
+
+	pub fn to_i128(&self) -> Option<i128> {
+		let magnitude = self.magnitude()?;
+		if self.negative {
+			0i128.checked_sub_unsigned(magnitude)
+		}
+		else {
+			i128::try_from(magnitude).ok()
+		}
+	}
+}
+
+impl From<i128> for BigInt {
+	fn from(n: i128) -> Self {
+		let mut magnitude = n.unsigned_abs();
+		let mut limbs = Vec::new();
+		while magnitude > 0 {
+			limbs.push(magnitude as u32);
+			magnitude >>= 32;
+		}
+		BigInt::new(n < 0, limbs)
+	}
+}
+
+impl PartialOrd for BigInt {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for BigInt {
+	fn cmp(&self, other: &Self) -> Ordering {
+		match (self.negative, other.negative) {
+			(false, true) => Ordering::Greater,
+			(true, false) => Ordering::Less,
+			(false, false) => compare(&self.limbs, &other.limbs),
+			(true, true) => compare(&other.limbs, &self.limbs),
+		}
+	}
+}
+
+impl fmt::Display for BigInt {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.limbs.is_empty() {
+			return f.pad_integral(true, "", "0");
+		}
+		let mut limbs = self.limbs.clone();
+		let mut chunks = Vec::new();
+		while !limbs.is_empty() {
+			chunks.push(div_small(&mut limbs, 1_000_000_000));
+		}
+		let mut digits = chunks.pop().unwrap().to_string();
+		for chunk in chunks.iter().rev() {
+			digits += &format!("{:09}", chunk);
+		}
+		f.pad_integral(!self.negative, "", &digits)
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseBigIntError(pub String);
+
+impl fmt::Display for ParseBigIntError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid integer `{}`", self.0)
+	}
+}
+
+impl Error for ParseBigIntError {}
+
+impl FromStr for BigInt {
+	type Err = ParseBigIntError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let (negative, digits) = match text.strip_prefix('-') {
+			Some(digits) => (true, digits),
+			None => (false, text.strip_prefix('+').unwrap_or(text)),
+		};
+		if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+			return Err(ParseBigIntError(text.to_string()));
+		}
+		let mut limbs = Vec::new();
+		for digit in digits.bytes() {
+			limbs = add(&mul(&limbs, &[10]), &[(digit - b'0') as u32]);
+		}
+		Ok(BigInt::new(negative, limbs))
+	}
+}
+
+impl Word for BigInt {
+	fn from_i64(n: i64) -> Self {
+		BigInt::from(n as i128)
+	}
+
+	fn to_i64(&self) -> Option<i64> {
+		self.to_i128().and_then(|n| i64::try_from(n).ok())
+	}
+
+	fn to_address(&self) -> Option<u128> {
+		if self.negative {
+			return None;
+		}
+		self.magnitude().filter(|m| *m <= MAX_ADDRESS)
+	}
+
+	fn plus(&self, other: &Self) -> Self {
+		if self.negative == other.negative {
+			return BigInt::new(self.negative, add(&self.limbs, &other.limbs));
+		}
+		match compare(&self.limbs, &other.limbs) {
+			Ordering::Less => BigInt::new(other.negative, sub(&other.limbs, &self.limbs)),
+			_ => BigInt::new(self.negative, sub(&self.limbs, &other.limbs)),
+		}
+	}
+
+	fn times(&self, other: &Self) -> Self {
+		BigInt::new(self.negative != other.negative, mul(&self.limbs, &other.limbs))
+	}
+
+	fn checked_plus(&self, other: &Self) -> Option<Self> {
+		Some(self.plus(other))
+	}
+
+	fn checked_times(&self, other: &Self) -> Option<Self> {
+		Some(self.times(other))
+	}
+
+	fn saturating_plus(&self, other: &Self) -> Self {
+		self.plus(other)
+	}
+
+	fn saturating_times(&self, other: &Self) -> Self {
+		self.times(other)
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn big(text: &str) -> BigInt {
+		text.parse().unwrap()
+	}
+
+	#[test]
+	fn bigint_test1() {
+		for n in [
+			0,
+			1,
+			-1,
+			999_999_999,
+			1_000_000_000,
+			-4_294_967_296,
+			i128::MAX,
+			i128::MIN,
+		] {
+			let b = BigInt::from(n);
+			assert_eq!(n.to_string(), b.to_string());
+			assert_eq!(b, big(&n.to_string()));
+			assert_eq!(Some(n), b.to_i128());
+		}
+		assert_eq!("-0", format!("-{}", big("-0")));
+		assert_eq!("123", big("+000123").to_string());
+		assert!("".parse::<BigInt>().is_err());
+		assert!("-".parse::<BigInt>().is_err());
+		assert!("12a".parse::<BigInt>().is_err());
+	}
+
+	#[test]
+	fn bigint_test2() {
+		let values = [-1_000_000_000_007i128, -5, 0, 3, 4_294_967_295, 1 << 70];
+		for a in values.iter() {
+			for b in values.iter() {
+				let (x, y) = (BigInt::from(*a), BigInt::from(*b));
+				assert_eq!(BigInt::from(a + b), x.plus(&y), "{} + {}", a, b);
+				assert_eq!(a.partial_cmp(b), x.partial_cmp(&y), "{} <=> {}", a, b);
+				if let Some(product) = a.checked_mul(*b) {
+					assert_eq!(BigInt::from(product), x.times(&y), "{} * {}", a, b);
+				}
+			}
+		}
+		let big_square = big("-100000000000000000000000000000").times(&big("100000000000000000000000000000"));
+		assert_eq!(format!("-1{}", "0".repeat(58)), big_square.to_string());
+		assert_eq!(None, big_square.to_i128());
+		assert_eq!(None, big_square.to_address());
+		assert_eq!(Some(1 << 100), big("1267650600228229401496703205376").to_address());
+		assert_eq!(Some(MAX_ADDRESS), BigInt::from(i128::MAX).to_address());
+		assert_eq!(None, big("340282366920938463463374607431768211455").to_address());
+	}
+}
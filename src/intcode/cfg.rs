@@ -0,0 +1,345 @@
+//! Control flow graph of a program as loaded, before it gets a chance to
+//! modify itself.
+//!
+//! Instructions are found by following the flow from address 0: past every
+//! instruction, and to the targets of jumps given as immediate operands.
+//! Jumps through memory cannot be followed. Two patterns of code that uses
+//! the relative base as a stack are recognised:
+//!
+//! ```text
+//! add #ret, #0, [rb+1]  ; call: the return address is pushed...
+//! jt #1, #function      ; ...right before jumping away
+//! ret:
+//! ...
+//! jf #0, [rb+1]         ; return: jump to an address on the stack
+//! ```
+
+use super::disassembler::{line_at, Line};
+use super::{Memory, Operation, ParameterMode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+////////////////////////////////////////
+// GRAPH
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum EdgeKind {
+	/// On to the next instruction.
+	Next,
+	/// Conditional jump, taken.
+	Branch,
+	/// Unconditional jump.
+	Jump,
+	Call,
+	/// From a call to where the function returns.
+	Return,
+}
+
+impl EdgeKind {
+	fn label(self) -> &'static str {
+		match self {
+			EdgeKind::Next => "",
+			EdgeKind::Branch => "branch",
+			EdgeKind::Jump => "jump",
+			EdgeKind::Call => "call",
+			EdgeKind::Return => "return",
+		}
+	}
+}
+
+/// How the last instruction of a block leaves it.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Exit {
+	Edges,
+	Halt,
+	/// A jump through memory, which looks like a function return.
+	Return,
+	/// Any other jump through memory.
+	Indirect,
+	/// Runs into something that is not an instruction.
+	Invalid,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+	pub start: usize,
+	pub lines: Vec<Line>,
+	pub exit: Exit,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Graph {
+	/// By start address.
+	pub blocks: BTreeMap<usize, Block>,
+	/// Between block start addresses.
+	pub edges: BTreeSet<(usize, usize, EdgeKind)>,
+	/// Addresses called as functions.
+	pub functions: BTreeSet<usize>,
+}
+
+////////////////////////////////////////
+// EXTRACTION
+////////////////////////////////////////
+
+/// What the flow does after one instruction.
+struct Flow {
+	line: Line,
+	targets: Vec<(usize, EdgeKind)>,
+	exit: Exit,
+}
+
+fn immediate(line: &Line, n: usize) -> Option<i128> {
+	match line {
+		Line::Instruction { modes, words, .. } if modes.get(n) == Some(&ParameterMode::Immediate) => Some(words[n + 1]),
+		_ => None,
+	}
+}
+
+/// The constant an instruction writes in relative mode, if any. `None` as
+/// well if working it out overflows.
+fn pushed(line: &Line) -> Option<i128> {
+	match line {
+		Line::Instruction { operation, modes, .. } if modes.get(2) == Some(&ParameterMode::Relative) => match operation
+		{
+			Operation::Add => immediate(line, 0)?.checked_add(immediate(line, 1)?),
+			Operation::Mul => immediate(line, 0)?.checked_mul(immediate(line, 1)?),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+fn flow(line: Line, previous: Option<&Line>) -> Flow {
+	let next = line.address() + line.words().len();
+	let (operation, modes) = match &line {
+		Line::Instruction { operation, modes, .. } => (*operation, modes.clone()),
+		Line::Data { .. } => {
+			return Flow {
+				line,
+				targets: Vec::new(),
+				exit: Exit::Invalid,
+			}
+		},
+	};
+	let jumps = match operation {
+		Operation::Halt => {
+			return Flow {
+				line,
+				targets: Vec::new(),
+				exit: Exit::Halt,
+			}
+		},
+		Operation::JumpIfTrue => immediate(&line, 0).map(|c| c != 0),
+		Operation::JumpIfFalse => immediate(&line, 0).map(|c| c == 0),
+		_ => Some(false),
+	};
+	let target = immediate(&line, 1).and_then(|t| usize::try_from(t).ok());
+
+	let mut targets = Vec::new();
+	let mut exit = Exit::Edges;
+	if jumps != Some(true) {
+		targets.push((next, EdgeKind::Next));
+	}
+	if jumps != Some(false) {
+		match target {
+			Some(target) if jumps.is_none() => targets.push((target, EdgeKind::Branch)),
+			Some(target) if previous.and_then(pushed) == Some(next as i128) => {
+				targets.push((target, EdgeKind::Call));
+				targets.push((next, EdgeKind::Return));
+			},
+			Some(target) => targets.push((target, EdgeKind::Jump)),
+			None if jumps.is_some() && modes[1] == ParameterMode::Relative => exit = Exit::Return,
+			None => exit = Exit::Indirect,
+		}
+	}
+	Flow { line, targets, exit }
+}
+
+pub fn build(code: &[i128]) -> Graph {
+	let memory = Memory::new(code.to_vec());
+	let mut flows: BTreeMap<usize, Flow> = BTreeMap::new();
+	let mut leaders = BTreeSet::new();
+	let mut pending = vec![0];
+	leaders.insert(0);
+
+	while let Some(start) = pending.pop() {
+		let mut address = start;
+		let mut previous: Option<Line> = None;
+		while address < code.len() && !flows.contains_key(&address) {
+			let flow = flow(line_at(&memory, address, code.len()), previous.as_ref());
+			let ends = flow.exit != Exit::Edges || flow.targets.iter().any(|(_, kind)| *kind != EdgeKind::Next);
+			for (target, kind) in &flow.targets {
+				if ends || *kind != EdgeKind::Next {
+					leaders.insert(*target);
+					pending.push(*target);
+				}
+			}
+			previous = Some(flow.line.clone());
+			let next = address + flow.line.words().len();
+			flows.insert(address, flow);
+			if ends {
+				break;
+			}
+			address = next;
+		}
+	}
+
+	let mut graph = Graph::default();
+	let mut current: Option<Block> = None;
+	for (address, flow) in flows {
+		let contiguous = current
+			.as_ref()
+			.and_then(|block| block.lines.last())
+			.is_some_and(|last| last.address() + last.words().len() == address);
+		if !contiguous || leaders.contains(&address) {
+			if let Some(block) = current.take() {
+				if contiguous {
+					graph.edges.insert((block.start, address, EdgeKind::Next));
+				}
+				graph.blocks.insert(block.start, block);
+			}
+			current = Some(Block {
+				start: address,
+				lines: Vec::new(),
+				exit: Exit::Edges,
+			});
+		}
+		let block = current.as_mut().unwrap();
+		block.lines.push(flow.line);
+		block.exit = flow.exit;
+		let ends = flow.exit != Exit::Edges || flow.targets.iter().any(|(_, kind)| *kind != EdgeKind::Next);
+		if ends {
+			for (target, kind) in flow.targets {
+				if target < code.len() {
+					graph.edges.insert((block.start, target, kind));
+				}
+				if kind == EdgeKind::Call {
+					graph.functions.insert(target);
+				}
+			}
+			let block = current.take().unwrap();
+			graph.blocks.insert(block.start, block);
+		}
+	}
+	if let Some(block) = current {
+		graph.blocks.insert(block.start, block);
+	}
+	graph
+}
+
+////////////////////////////////////////
+// DOT
+////////////////////////////////////////
+
+impl Graph {
+	/// The graph in Graphviz syntax. Functions are drawn with a double
+	/// border and returns in grey.
+	pub fn dot(&self) -> String {
+		let mut out = String::from("digraph intcode {\n\tnode [shape=box, fontname=\"monospace\"];\n");
+		for block in self.blocks.values() {
+			let mut label: String = block
+				.lines
+				.iter()
+				.map(|line| format!("{:>5}  {}\\l", line.address(), line.text()))
+				.collect();
+			match block.exit {
+				Exit::Halt | Exit::Edges => {},
+				Exit::Return => label += "(return)\\l",
+				Exit::Indirect => label += "(indirect jump)\\l",
+				Exit::Invalid => label += "(invalid instruction)\\l",
+			}
+			let mut style = String::new();
+			if self.functions.contains(&block.start) {
+				style += ", peripheries=2";
+			}
+			if block.exit == Exit::Return {
+				style += ", style=filled, fillcolor=lightgrey";
+			}
+			writeln!(
+				out,
+				"\tb{} [label=\"{}\"{}];",
+				block.start,
+				label.replace('"', "\\\""),
+				style
+			)
+			.unwrap();
+		}
+		for (from, to, kind) in &self.edges {
+			match kind {
+				EdgeKind::Next => writeln!(out, "\tb{} -> b{};", from, to).unwrap(),
+				EdgeKind::Return => writeln!(out, "\tb{} -> b{} [label=\"return\", style=dashed];", from, to).unwrap(),
+				_ => writeln!(out, "\tb{} -> b{} [label=\"{}\"];", from, to, kind.label()).unwrap(),
+			}
+		}
+		out + "}\n"
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::assembler::assemble;
+	use super::*;
+
+	fn edges(graph: &Graph) -> Vec<(usize, usize, EdgeKind)> {
+		graph.edges.iter().copied().collect()
+	}
+
+	#[test]
+	fn cfg_test1() {
+		let code = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+		let graph = build(&code);
+		assert_eq!(vec![0, 15], graph.blocks.keys().copied().collect::<Vec<_>>());
+		assert_eq!(5, graph.blocks[&0].lines.len());
+		assert_eq!(Exit::Halt, graph.blocks[&15].exit);
+		assert_eq!(vec![(0, 0, EdgeKind::Branch), (0, 15, EdgeKind::Next)], edges(&graph));
+	}
+
+	#[test]
+	fn cfg_test2() {
+		let code = assemble(
+			"
+			        arb #100
+			        add #back, #0, [rb+1]
+			        jt #1, #double
+			back:   out [200]
+			        hlt
+			double: mul [200], #2, [200]
+			        jf #0, [rb+1]
+			",
+		)
+		.unwrap();
+		let graph = build(&code);
+		assert_eq!(vec![0, 9, 12], graph.blocks.keys().copied().collect::<Vec<_>>());
+		assert_eq!(vec![(0, 9, EdgeKind::Return), (0, 12, EdgeKind::Call)], edges(&graph));
+		assert_eq!(vec![12], graph.functions.iter().copied().collect::<Vec<_>>());
+		assert_eq!(Exit::Return, graph.blocks[&12].exit);
+
+		let dot = graph.dot();
+		assert!(dot.starts_with("digraph intcode {\n"));
+		assert!(dot.contains(
+			"\tb12 [label=\"   12  mul [200], #2, [200]\\l   16  jf #0, [rb+1]\\l(return)\\l\", peripheries=2"
+		));
+		assert!(dot.contains("\tb0 -> b12 [label=\"call\"];\n"));
+		assert!(dot.ends_with("}\n"));
+	}
+
+	#[test]
+	fn cfg_test3() {
+		// A jump through position mode, and one past the end
+		let graph = build(&[1105, 1, 4, 99, 5, 0, 0, 1106, 0, 10]);
+		assert_eq!(vec![0, 4, 7], graph.blocks.keys().copied().collect::<Vec<_>>());
+		assert_eq!(Exit::Indirect, graph.blocks[&4].exit);
+		assert_eq!(vec![(0, 4, EdgeKind::Jump), (4, 7, EdgeKind::Next)], edges(&graph));
+		assert_eq!(Exit::Invalid, build(&[1, 0, 0]).blocks[&0].exit);
+		// A return address too large to work out
+		let graph = build(&[21101, i128::MAX, 1, 1, 1105, 1, 0, 99]);
+		assert_eq!(vec![0], graph.blocks.keys().copied().collect::<Vec<_>>());
+	}
+}
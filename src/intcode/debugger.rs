@@ -0,0 +1,397 @@
+//! Command interpreter behind the `intcode-dbg` binary.
+
+use super::disassembler::line_at;
+use super::history::History;
+use super::{ExitCode, Machine, MAX_ADDRESS};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+const HELP: &str = "\
+step [n]            execute n instructions (default 1)
+continue            run until a breakpoint, a watchpoint, an input request or halt
+back [n]            undo the last n instructions (default 1)
+rewind <addr>|out   run backwards to the last write to addr, or the last output
+break <addr>        stop before executing the instruction at addr
+delete <addr>       remove the breakpoint at addr
+watch <addr>        stop after the value at addr changes
+unwatch <addr>      remove the watchpoint at addr
+info                list breakpoints and watchpoints
+regs                print ip, relative base and the input/output queues
+list [addr] [n]     disassemble n instructions from addr (default: ip, 5)
+x <addr> [n]        print n memory cells from addr (default 1)
+set <addr> <value>  patch a memory cell
+set ip|rb <value>   move the instruction pointer or the relative base
+input <values...>   queue input values
+save <file>         write a snapshot of the machine
+load <file>         resume from a snapshot
+quit                leave the debugger
+An empty line repeats the last command.";
+
+////////////////////////////////////////
+// DEBUGGER
+////////////////////////////////////////
+
+pub struct Debugger {
+	pub machine: Machine,
+	pub breakpoints: BTreeSet<u128>,
+	pub watchpoints: BTreeSet<u128>,
+	/// Everything executed since the last time the machine was patched.
+	pub history: History,
+	last_command: String,
+	printed: usize,
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&&str>, default: Option<T>) -> Result<T, String> {
+	match arg {
+		Some(text) => text.parse().map_err(|_| format!("invalid number `{}`", text)),
+		None => default.ok_or_else(|| String::from("missing argument")),
+	}
+}
+
+fn parse_address(arg: Option<&&str>, default: Option<u128>) -> Result<u128, String> {
+	parse(arg, default).and_then(|address| match address {
+		0..=MAX_ADDRESS => Ok(address),
+		_ => Err(format!("address {} is past the end of memory", address)),
+	})
+}
+
+impl Debugger {
+	pub fn new(machine: Machine) -> Self {
+		Self {
+			machine,
+			breakpoints: BTreeSet::new(),
+			watchpoints: BTreeSet::new(),
+			history: History::default(),
+			last_command: String::new(),
+			printed: 0,
+		}
+	}
+
+	fn cell(&self, address: u128) -> i128 {
+		self.machine.memory.cell(address)
+	}
+
+	/// Runs one instruction and describes why execution should stop, if it
+	/// should.
+	fn step(&mut self) -> Option<String> {
+		let watched: Vec<(u128, i128)> = self.watchpoints.iter().map(|a| (*a, self.cell(*a))).collect();
+		let ip = self.machine.ip;
+		match self.machine.step_recorded(&mut self.history) {
+			Err(error) => return Some(format!("fault: {}", error)),
+			Ok(Some(ExitCode::Halt)) => return Some(format!("halted at {}", ip)),
+			Ok(Some(ExitCode::Wait)) => return Some(format!("waiting for input at {}", ip)),
+			Ok(_) => {},
+		}
+		for (address, old) in watched {
+			let new = self.cell(address);
+			if new != old {
+				return Some(format!(
+					"watchpoint {}: {} -> {} (written at {})",
+					address, old, new, ip
+				));
+			}
+		}
+		None
+	}
+
+	/// Stops after `steps` instructions if given, at a breakpoint otherwise.
+	fn run(&mut self, steps: Option<usize>) -> String {
+		let mut executed = 0;
+		let reason = loop {
+			if let Some(reason) = self.step() {
+				break reason;
+			}
+			executed += 1;
+			if steps == Some(executed) {
+				break self.list(self.machine.ip, 1).unwrap_or_else(|error| error);
+			}
+			if steps.is_none() && self.breakpoints.contains(&self.machine.ip) {
+				break format!("breakpoint at {}", self.machine.ip);
+			}
+		};
+		self.flush_output() + &reason
+	}
+
+	/// New values the program has written since the last command.
+	fn flush_output(&mut self) -> String {
+		let new = &self.machine.output[self.printed..];
+		self.printed = self.machine.output.len();
+		new.iter().map(|value| format!("output: {}\n", value)).collect()
+	}
+
+	fn list(&self, from: u128, n: usize) -> Result<String, String> {
+		let mut address = usize::try_from(from).map_err(|_| format!("cannot list from {}", from))?;
+		let mut lines = Vec::new();
+		for _ in 0..n {
+			let line = line_at(&self.machine.memory, address, usize::MAX);
+			let marker = if address as u128 == self.machine.ip { "=>" } else { "  " };
+			lines.push(format!("{} {}", marker, line));
+			match address.checked_add(line.words().len()) {
+				Some(next) => address = next,
+				None => break,
+			}
+		}
+		Ok(lines.join("\n"))
+	}
+
+	fn rewound(&mut self, ip: Option<u128>) -> Result<String, String> {
+		self.printed = self.printed.min(self.machine.output.len());
+		ip.ok_or_else(|| String::from("not in the history"))
+			.and_then(|ip| self.list(ip, 1))
+	}
+
+	fn regs(&self) -> String {
+		format!(
+			"ip: {}\nrb: {}\ninput: {:?}\noutput: {:?}",
+			self.machine.ip, self.machine.memory.base, self.machine.input, self.machine.output
+		)
+	}
+
+	fn info(&self) -> String {
+		format!(
+			"breakpoints: {:?}\nwatchpoints: {:?}",
+			self.breakpoints, self.watchpoints
+		)
+	}
+
+	/// Executes one command line. Returns the text to show the user, or
+	/// `None` once the user asks to quit.
+	pub fn execute(&mut self, line: &str) -> Option<String> {
+		let line = if line.trim().is_empty() {
+			self.last_command.clone()
+		}
+		else {
+			line.trim().to_string()
+		};
+		self.last_command = line.clone();
+
+		let words: Vec<&str> = line.split_whitespace().collect();
+		let args = &words[1.min(words.len())..];
+		let reply = match words.first().copied().unwrap_or("") {
+			"" => Ok(String::new()),
+			"s" | "step" => parse(args.first(), Some(1)).and_then(|n| match n {
+				0 => Err(String::from("cannot step 0 instructions")),
+				n => Ok(self.run(Some(n))),
+			}),
+			"c" | "continue" => Ok(self.run(None)),
+			"back" => parse(args.first(), Some(1)).and_then(|n| {
+				let undone = self.machine.step_back(&mut self.history, n);
+				let ip = Some(self.machine.ip).filter(|_| undone > 0);
+				self.rewound(ip)
+			}),
+			"rewind" => match args.first() {
+				Some(&"out") => {
+					let ip = self.machine.back_to_output(&mut self.history);
+					self.rewound(ip)
+				},
+				_ => parse_address(args.first(), None).and_then(|a| {
+					let ip = self.machine.back_to_write(&mut self.history, a);
+					self.rewound(ip)
+				}),
+			},
+			"b" | "break" => parse_address(args.first(), None).map(|a| {
+				self.breakpoints.insert(a);
+				self.info()
+			}),
+			"d" | "delete" => parse_address(args.first(), None).map(|a| {
+				self.breakpoints.remove(&a);
+				self.info()
+			}),
+			"w" | "watch" => parse_address(args.first(), None).map(|a| {
+				self.watchpoints.insert(a);
+				self.info()
+			}),
+			"unwatch" => parse_address(args.first(), None).map(|a| {
+				self.watchpoints.remove(&a);
+				self.info()
+			}),
+			"i" | "info" => Ok(self.info()),
+			"r" | "regs" => Ok(self.regs()),
+			"l" | "list" => parse_address(args.first(), Some(self.machine.ip))
+				.and_then(|a| parse(args.get(1), Some(5)).and_then(|n| self.list(a, n))),
+			"x" => parse_address(args.first(), None).and_then(|a| {
+				parse(args.get(1), Some(1)).and_then(|n: u128| {
+					let end = a.checked_add(n).filter(|end| *end <= MAX_ADDRESS + 1);
+					let end = end.ok_or_else(|| format!("{} cells from {} go past the end of memory", n, a))?;
+					Ok((a..end)
+						.map(|i| format!("{}: {}", i, self.cell(i)))
+						.collect::<Vec<_>>()
+						.join("\n"))
+				})
+			}),
+			"set" => match args.first() {
+				Some(&"ip") => parse_address(args.get(1), None).map(|ip| {
+					self.machine.ip = ip;
+					self.history.clear();
+					self.regs()
+				}),
+				Some(&"rb") => parse(args.get(1), None).map(|rb| {
+					self.machine.memory.base = rb;
+					self.history.clear();
+					self.regs()
+				}),
+				_ => parse_address(args.first(), None).and_then(|a| {
+					parse(args.get(1), None).map(|value| {
+						self.machine.memory.set(a, value);
+						self.history.clear();
+						format!("{}: {}", a, value)
+					})
+				}),
+			},
+			"input" => args
+				.iter()
+				.map(|arg| parse(Some(arg), None))
+				.collect::<Result<Vec<i128>, _>>()
+				.map(|values| {
+					self.machine.input.extend(values);
+					format!("input: {:?}", self.machine.input)
+				}),
+			"save" => args
+				.first()
+				.ok_or_else(|| String::from("missing argument"))
+				.and_then(|path| self.machine.save_to(path).map_err(|e| e.to_string()))
+				.map(|_| format!("saved at ip {}", self.machine.ip)),
+			"load" => args
+				.first()
+				.ok_or_else(|| String::from("missing argument"))
+				.and_then(|path| Machine::load_from(path).map_err(|e| e.to_string()))
+				.map(|machine| {
+					self.machine = machine;
+					self.history.clear();
+					self.printed = self.machine.output.len();
+					self.regs()
+				}),
+			"h" | "help" => Ok(HELP.to_string()),
+			"q" | "quit" => return None,
+			command => Err(format!("unknown command `{}`, try `help`", command)),
+		};
+		Some(reply.unwrap_or_else(|error| format!("error: {}", error)))
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn quine() -> Debugger {
+		let code = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+		Debugger::new(Machine::new(code))
+	}
+
+	#[test]
+	fn debugger_test1() {
+		let mut dbg = quine();
+		assert_eq!(
+			"=>      2  204,-1                        out [rb-1]",
+			dbg.execute("step").unwrap()
+		);
+		assert_eq!(
+			"output: 109\n=>      4  1001,100,1,100                add [100], #1, [100]",
+			dbg.execute("").unwrap()
+		);
+		assert_eq!("ip: 4\nrb: 1\ninput: []\noutput: [109]", dbg.execute("regs").unwrap());
+	}
+
+	#[test]
+	fn debugger_test2() {
+		let mut dbg = quine();
+		dbg.execute("break 12");
+		assert_eq!("output: 109\nbreakpoint at 12", dbg.execute("continue").unwrap());
+		assert_eq!("output: 1\nbreakpoint at 12", dbg.execute("c").unwrap());
+		dbg.execute("delete 12");
+		dbg.execute("watch 100");
+		assert_eq!(
+			"output: 204\nwatchpoint 100: 2 -> 3 (written at 4)",
+			dbg.execute("c").unwrap()
+		);
+		assert_eq!(None, dbg.execute("quit"));
+	}
+
+	#[test]
+	fn debugger_test3() {
+		let mut dbg = Debugger::new(Machine::new(vec![3, 0, 4, 0, 99]));
+		assert_eq!("waiting for input at 0", dbg.execute("c").unwrap());
+		dbg.execute("input 7");
+		assert_eq!("output: 7\nhalted at 4", dbg.execute("c").unwrap());
+		dbg.execute("set 1 3");
+		dbg.execute("set ip 0");
+		assert_eq!("0: 7\n1: 3", dbg.execute("x 0 2").unwrap());
+		assert!(dbg.execute("frobnicate").unwrap().starts_with("error"));
+	}
+
+	#[test]
+	fn debugger_test4() {
+		let path = std::env::temp_dir().join(format!("intcode-debugger-test4-{}", std::process::id()));
+		let path = path.to_str().unwrap();
+		let mut dbg = quine();
+		dbg.execute("step 4");
+		assert_eq!("saved at ip 12", dbg.execute(&format!("save {}", path)).unwrap());
+		dbg.execute("step 10");
+		assert_eq!(
+			"ip: 12\nrb: 1\ninput: []\noutput: [109]",
+			dbg.execute(&format!("load {}", path)).unwrap()
+		);
+		std::fs::remove_file(path).unwrap();
+		assert!(dbg.execute("load").unwrap().starts_with("error"));
+	}
+
+	#[test]
+	fn debugger_test5() {
+		let mut dbg = quine();
+		dbg.execute("step 10");
+		assert_eq!(
+			"=>      4  1001,100,1,100                add [100], #1, [100]",
+			dbg.execute("rewind 100").unwrap()
+		);
+		assert_eq!(
+			"=>      2  204,-1                        out [rb-1]",
+			dbg.execute("rewind out").unwrap()
+		);
+		assert_eq!("ip: 2\nrb: 2\ninput: []\noutput: [109]", dbg.execute("regs").unwrap());
+		assert_eq!(
+			"output: 1\n=>      4  1001,100,1,100                add [100], #1, [100]",
+			dbg.execute("step").unwrap()
+		);
+		assert_eq!(
+			"=>      0  109,1                         arb #1",
+			dbg.execute("back 100").unwrap()
+		);
+		assert_eq!("error: not in the history", dbg.execute("back").unwrap());
+	}
+
+	#[test]
+	fn debugger_test6() {
+		let mut dbg = quine();
+		assert_eq!("error: cannot step 0 instructions", dbg.execute("step 0").unwrap());
+		assert_eq!(0, dbg.machine.ip);
+		dbg.execute("break 0");
+		assert_eq!("output: 109\nbreakpoint at 0", dbg.execute("c").unwrap());
+		let max = u128::MAX.to_string();
+		for command in [
+			format!("set {} 1", max),
+			format!("x {} 2", max),
+			format!("break {}", max),
+			format!("set ip {}", max),
+		] {
+			assert_eq!(
+				format!("error: address {} is past the end of memory", max),
+				dbg.execute(&command).unwrap()
+			);
+		}
+		assert_eq!(
+			"error: 2 cells from 170141183460469231731687303715884105727 go past the end of memory",
+			dbg.execute("x 170141183460469231731687303715884105727 2").unwrap()
+		);
+		let listed = dbg.execute(&format!("list {} 2", usize::MAX)).unwrap();
+		assert_eq!(1, listed.lines().count());
+		assert!(dbg
+			.execute("list 170141183460469231731687303715884105727")
+			.unwrap()
+			.starts_with("error"));
+	}
+}
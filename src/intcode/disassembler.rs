@@ -0,0 +1,189 @@
+//! Turns a program back into the syntax understood by `assembler`.
+//!
+//! Programs are decoded with a linear sweep. Words that cannot start a valid
+//! instruction (unknown opcode, bad parameter mode, a write in immediate mode
+//! or an instruction cut short by the end of the program) are listed as
+//! `.data`, and decoding carries on with the next word.
+
+use super::{Instruction, Memory, Operation, ParameterMode};
+use std::fmt;
+
+////////////////////////////////////////
+// LINE
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Line {
+	Instruction {
+		address: usize,
+		operation: Operation,
+		modes: Vec<ParameterMode>,
+		words: Vec<i128>,
+	},
+	Data {
+		address: usize,
+		value: i128,
+	},
+}
+
+impl Line {
+	pub fn address(&self) -> usize {
+		match self {
+			Line::Instruction { address, .. } | Line::Data { address, .. } => *address,
+		}
+	}
+
+	pub fn words(&self) -> &[i128] {
+		match self {
+			Line::Instruction { words, .. } => words,
+			Line::Data { value, .. } => std::slice::from_ref(value),
+		}
+	}
+
+	/// Just the assembly, without address and raw words.
+	pub fn text(&self) -> String {
+		match self {
+			Line::Instruction {
+				operation,
+				modes,
+				words,
+				..
+			} => {
+				let operands: Vec<String> = modes.iter().zip(&words[1..]).map(|(m, w)| operand(*m, *w)).collect();
+				if operands.is_empty() {
+					operation.mnemonic().to_string()
+				}
+				else {
+					format!("{} {}", operation.mnemonic(), operands.join(", "))
+				}
+			},
+			Line::Data { value, .. } => format!(".data {}", value),
+		}
+	}
+}
+
+/// Renders an operand the way the assembler reads it.
+pub fn operand(mode: ParameterMode, value: i128) -> String {
+	match mode {
+		ParameterMode::Position => format!("[{}]", value),
+		ParameterMode::Immediate => format!("#{}", value),
+		ParameterMode::Relative if value == 0 => String::from("[rb]"),
+		ParameterMode::Relative if value < 0 => format!("[rb{}]", value),
+		ParameterMode::Relative => format!("[rb+{}]", value),
+	}
+}
+
+impl fmt::Display for Line {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let raw = self.words().iter().map(|w| w.to_string()).collect::<Vec<_>>().join(",");
+		write!(f, "{:>6}  {:<28}  {}", self.address(), raw, self.text())
+	}
+}
+
+////////////////////////////////////////
+// DISASSEMBLER
+////////////////////////////////////////
+
+fn decode(memory: &Memory, address: usize, len: usize) -> Option<Instruction> {
+	let ins = Instruction::parse(address as u128, memory).ok()?;
+	if address
+		.checked_add(ins.opcode.n_parameters())
+		.is_none_or(|last| last >= len)
+	{
+		return None;
+	}
+	if let Some(n) = ins.opcode.output_parameter() {
+		if ins.parameter_modes[n] == ParameterMode::Immediate {
+			return None;
+		}
+	}
+	Some(ins)
+}
+
+/// Decodes whatever is stored at `address`, as an instruction if possible.
+/// Nothing at or after `end` is considered part of the program.
+pub fn line_at(memory: &Memory, address: usize, end: usize) -> Line {
+	let word = |i: usize| memory.cell(i as u128);
+	match decode(memory, address, end) {
+		Some(ins) => {
+			let len = ins.opcode.n_parameters() + 1;
+			Line::Instruction {
+				address,
+				operation: ins.opcode,
+				modes: ins.modes().to_vec(),
+				words: (address..address + len).map(word).collect(),
+			}
+		},
+		None => Line::Data {
+			address,
+			value: word(address),
+		},
+	}
+}
+
+pub fn disassemble(code: &[i128]) -> Vec<Line> {
+	let memory = Memory::new(code.to_vec());
+	let mut lines = Vec::new();
+	let mut address = 0;
+	while address < code.len() {
+		let line = line_at(&memory, address, code.len());
+		address += line.words().len();
+		lines.push(line);
+	}
+	lines
+}
+
+/// The whole listing, one line per instruction or data word.
+pub fn listing(code: &[i128]) -> String {
+	disassemble(code).iter().map(|line| format!("{}\n", line)).collect()
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::assembler::assemble;
+	use super::*;
+
+	#[test]
+	fn disassembler_test1() {
+		let code = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+		let expected = [
+			"     0  109,1                         arb #1",
+			"     2  204,-1                        out [rb-1]",
+			"     4  1001,100,1,100                add [100], #1, [100]",
+			"     8  1008,100,16,101               eq [100], #16, [101]",
+			"    12  1006,101,0                    jf [101], #0",
+			"    15  99                            hlt",
+		];
+		assert_eq!(expected.join("\n") + "\n", listing(&code));
+	}
+
+	#[test]
+	fn disassembler_test2() {
+		let code = vec![
+			3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125,
+			20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+		];
+		let lines = disassemble(&code);
+		let data: Vec<usize> = lines
+			.iter()
+			.filter(|line| matches!(line, Line::Data { .. }))
+			.map(Line::address)
+			.collect();
+		assert_eq!(vec![19, 20, 21, 45], data);
+
+		let source: String = lines.iter().map(|line| line.text() + "\n").collect();
+		assert_eq!(code, assemble(&source).unwrap());
+	}
+
+	#[test]
+	fn disassembler_test3() {
+		let lines = disassemble(&[11101, 1, 1, 0]);
+		assert!(lines.iter().all(|line| matches!(line, Line::Data { .. })));
+		assert_eq!(4, lines.len());
+	}
+}
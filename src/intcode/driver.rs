@@ -0,0 +1,356 @@
+//! Shorthands for setting up a machine and trading values with it, instead
+//! of pushing onto `input`, running until the right `ExitCode` and popping
+//! `output` by hand.
+//!
+//! ```ignore
+//! let mut machine = Machine::builder(code).patch(1, noun).patch(2, verb).build();
+//! let outputs = machine.run_with(vec![5])?;
+//! let reply = machine.call(vec![x, y], 1)?;
+//! ```
+
+use super::{Arithmetic, ExitCode, IntcodeError, Limits, Machine, Word};
+use std::error::Error;
+use std::fmt;
+use std::iter;
+
+////////////////////////////////////////
+// BUILDER
+////////////////////////////////////////
+
+#[derive(Clone)]
+pub struct Builder<W = i128> {
+	code: Vec<W>,
+	patches: Vec<(u128, W)>,
+	inputs: Vec<W>,
+	arithmetic: Arithmetic,
+	limits: Limits,
+	optimize: bool,
+}
+
+impl Machine {
+	pub fn builder(code: Vec<i128>) -> Builder {
+		Builder::new(code)
+	}
+}
+
+impl<W: Word> Builder<W> {
+	pub fn new(code: Vec<W>) -> Self {
+		Builder {
+			code,
+			patches: Vec::new(),
+			inputs: Vec::new(),
+			arithmetic: Arithmetic::default(),
+			limits: Limits::default(),
+			optimize: false,
+		}
+	}
+
+	/// Stores `value` at `address` before the program starts.
+	pub fn patch(mut self, address: u128, value: W) -> Self {
+		self.patches.push((address, value));
+		self
+	}
+
+	/// Queues inputs, after any queued before.
+	pub fn inputs<I: IntoIterator<Item = W>>(mut self, inputs: I) -> Self {
+		self.inputs.extend(inputs);
+		self
+	}
+
+	pub fn arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+		self.arithmetic = arithmetic;
+		self
+	}
+
+	pub fn limits(mut self, limits: Limits) -> Self {
+		self.limits = limits;
+		self
+	}
+
+	/// Ignored while any of the `limits` is set, see `Machine::optimize`.
+	pub fn optimize(mut self, optimize: bool) -> Self {
+		self.optimize = optimize;
+		self
+	}
+
+	pub fn build(self) -> Machine<W> {
+		let mut machine = Machine::from_code(self.code);
+		for (address, value) in self.patches {
+			machine.memory.set(address, value);
+		}
+		machine.input.extend(self.inputs);
+		machine.arithmetic = self.arithmetic;
+		machine.limits = self.limits;
+		machine.optimize = self.optimize;
+		machine
+	}
+}
+
+////////////////////////////////////////
+// OUTPUTS
+////////////////////////////////////////
+
+/// Runs the machine only as far as it takes to get each output. See
+/// `Machine::outputs`. The outputs it gave out are taken off the machine
+/// once it is dropped.
+pub struct Outputs<'a, W, I> {
+	machine: &'a mut Machine<W>,
+	inputs: I,
+	/// How many of the machine's outputs were given out already. They are
+	/// only removed once all are, or the iteration ends.
+	taken: usize,
+	failed: bool,
+}
+
+impl<'a, W: Word, I: Iterator<Item = W>> Outputs<'a, W, I> {
+	/// Queues an input for the machine, e.g. after it ran out of them.
+	pub fn feed(&mut self, value: W) {
+		self.machine.input.push_back(value);
+	}
+}
+
+impl<'a, W: Word, I: Iterator<Item = W>> Iterator for Outputs<'a, W, I> {
+	type Item = Result<W, IntcodeError<W>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.failed {
+			return None;
+		}
+		loop {
+			if let Some(value) = self.machine.output.get(self.taken) {
+				self.taken += 1;
+				return Some(Ok(value.clone()));
+			}
+			self.machine.output.clear();
+			self.taken = 0;
+			match self.machine.run() {
+				Ok(ExitCode::Print) => {},
+				Ok(ExitCode::Wait) => self.machine.input.push_back(self.inputs.next()?),
+				Ok(ExitCode::Halt) => return None,
+				Err(error) => {
+					self.failed = true;
+					return Some(Err(error));
+				},
+			}
+		}
+	}
+}
+
+impl<'a, W, I> Drop for Outputs<'a, W, I> {
+	fn drop(&mut self) {
+		self.machine.output.drain(..self.taken);
+	}
+}
+
+////////////////////////////////////////
+// CALLS
+////////////////////////////////////////
+
+/// Why `Machine::call` failed, with the outputs it got before that.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallError<W = i128> {
+	pub outputs: Vec<W>,
+	pub error: IntcodeError<W>,
+}
+
+impl<W: Word> fmt::Display for CallError<W> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} after {} outputs", self.error, self.outputs.len())
+	}
+}
+
+impl<W: Word> Error for CallError<W> {}
+
+////////////////////////////////////////
+// MACHINE
+////////////////////////////////////////
+
+impl<W: Word> Machine<W> {
+	/// The outputs, produced as they are asked for. Ends when the program
+	/// halts or waits for an input nobody queued, after which `feed` can
+	/// give it one and the iteration go on. A fault is the last item.
+	pub fn outputs(&mut self) -> Outputs<'_, W, iter::Empty<W>> {
+		self.outputs_from(iter::empty())
+	}
+
+	/// Like `outputs`, taking each input from `inputs` only when the
+	/// program asks for it.
+	pub fn outputs_from<I: IntoIterator<Item = W>>(&mut self, inputs: I) -> Outputs<'_, W, I::IntoIter> {
+		Outputs {
+			machine: self,
+			inputs: inputs.into_iter(),
+			taken: 0,
+			failed: false,
+		}
+	}
+
+	/// Queues `inputs` and runs until the program halts or needs more of
+	/// them. Returns every output not taken yet.
+	pub fn run_with<I: IntoIterator<Item = W>>(&mut self, inputs: I) -> Result<Vec<W>, IntcodeError<W>> {
+		self.input.extend(inputs);
+		loop {
+			match self.run()? {
+				ExitCode::Print => {},
+				ExitCode::Halt | ExitCode::Wait => return Ok(self.output.drain(..).collect()),
+			}
+		}
+	}
+
+	/// Queues `inputs` and runs until there are `n_outputs` outputs to give
+	/// back, for programs that answer requests. Running out of inputs or
+	/// halting before that is an error, which comes with the outputs there
+	/// were.
+	pub fn call<I: IntoIterator<Item = W>>(&mut self, inputs: I, n_outputs: usize) -> Result<Vec<W>, CallError<W>> {
+		self.input.extend(inputs);
+		while self.output.len() < n_outputs {
+			let error = match self.run() {
+				Ok(ExitCode::Print) => continue,
+				Ok(ExitCode::Wait) => IntcodeError::InputExhausted {
+					ip: self.ip,
+					excerpt: self.memory.excerpt(self.ip),
+				},
+				Ok(ExitCode::Halt) => IntcodeError::Halted {
+					ip: self.ip,
+					excerpt: self.memory.excerpt(self.ip),
+				},
+				Err(error) => error,
+			};
+			return Err(CallError {
+				outputs: self.output.drain(..).collect(),
+				error,
+			});
+		}
+		Ok(self.output.drain(..n_outputs).collect())
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::bigint::BigInt;
+	use super::super::fixtures::adder;
+	use super::*;
+
+	/// Reads pairs and answers with their sum and product, forever.
+	fn calculator() -> Vec<i128> {
+		vec![
+			3, 100, 3, 101, 1, 100, 101, 102, 2, 100, 101, 103, 4, 102, 4, 103, 1105, 1, 0,
+		]
+	}
+
+	#[test]
+	fn driver_test1() {
+		// [0] = [1] + [2]
+		let mut machine = Machine::builder(vec![1, 0, 0, 0, 99, 20, 22])
+			.patch(1, 5)
+			.patch(2, 6)
+			.build();
+		machine.run_until_halt().unwrap();
+		assert_eq!(vec![42, 5, 6, 0, 99, 20, 22], machine.memory.to_vec());
+
+		let machine = Machine::builder(adder())
+			.inputs(vec![1])
+			.inputs([2, 0])
+			.arithmetic(Arithmetic::Wrapping)
+			.optimize(true)
+			.build();
+		assert_eq!(vec![1, 2, 0], machine.input.iter().cloned().collect::<Vec<_>>());
+		assert_eq!(Arithmetic::Wrapping, machine.arithmetic);
+		assert!(machine.optimize);
+
+		let mut machine = Builder::new(vec![
+			BigInt::from(1102),
+			BigInt::from(3),
+			BigInt::from(4),
+			BigInt::from(5),
+		])
+		.patch(4, BigInt::from(99))
+		.build();
+		machine.run_until_halt().unwrap();
+		assert_eq!(BigInt::from(12), machine.memory.cell(5));
+	}
+
+	#[test]
+	fn driver_test2() {
+		let mut machine = Machine::new(adder());
+		assert_eq!(Ok(vec![1, 3]), machine.run_with(vec![1, 2]));
+		assert_eq!(Ok(vec![]), machine.run_with(vec![]));
+		assert_eq!(Ok(vec![6]), machine.run_with(vec![3, 0]));
+
+		let mut machine = Machine::new(adder());
+		let mut outputs = machine.outputs_from(vec![1, 2, 3]);
+		assert_eq!(Some(Ok(1)), outputs.next());
+		assert_eq!(Some(Ok(3)), outputs.next());
+		assert_eq!(Some(Ok(6)), outputs.next());
+		assert_eq!(None, outputs.next());
+		outputs.feed(4);
+		outputs.feed(0);
+		assert_eq!(Some(Ok(10)), outputs.next());
+		assert_eq!(None, outputs.next());
+		drop(outputs);
+		assert_eq!(Ok(vec![]), machine.run_with(vec![]));
+
+		// Only as many inputs are taken as the outputs need
+		let mut machine = Machine::new(adder());
+		let mut inputs = vec![1, 1, 1].into_iter();
+		let firsts: Vec<_> = machine.outputs_from(&mut inputs).take(2).collect();
+		assert_eq!(vec![Ok(1), Ok(2)], firsts);
+		assert_eq!(Some(1), inputs.next());
+
+		// Outputs not taken stay with the machine
+		let mut machine = Machine::new(vec![104, 1, 104, 2, 104, 3, 99]);
+		machine.run().unwrap();
+		machine.run().unwrap();
+		assert_eq!(Some(Ok(1)), machine.outputs().next());
+		assert_eq!(vec![2], machine.output);
+		let all: Result<Vec<_>, _> = machine.outputs().collect();
+		assert_eq!(Ok(vec![2, 3]), all);
+		assert!(machine.output.is_empty());
+
+		let mut machine = Machine::new(vec![104, 1, 104, 2, 99]);
+		let outputs: Result<Vec<_>, _> = machine.outputs().collect();
+		assert_eq!(Ok(vec![1, 2]), outputs);
+
+		let mut machine = Machine::new(vec![104, 1, 98]);
+		let mut outputs = machine.outputs();
+		assert_eq!(Some(Ok(1)), outputs.next());
+		assert!(matches!(
+			outputs.next(),
+			Some(Err(IntcodeError::UnknownOpcode { ip: 2, .. }))
+		));
+		assert_eq!(None, outputs.next());
+	}
+
+	#[test]
+	fn driver_test3() {
+		let mut machine = Machine::new(calculator());
+		assert_eq!(Ok(vec![5, 6]), machine.call(vec![2, 3], 2));
+		// Outputs left over are the first to come back next time
+		assert_eq!(Ok(vec![-1]), machine.call(vec![-2, 1], 1));
+		assert_eq!(Ok(vec![-2, 6]), machine.call(vec![7, -1], 2));
+		assert_eq!(Ok(vec![-7]), machine.call(vec![], 1));
+		let error = machine.call(vec![1], 1).unwrap_err();
+		assert!(matches!(error.error, IntcodeError::InputExhausted { ip: 2, .. }));
+		assert!(error.outputs.is_empty());
+
+		let mut machine = Machine::new(vec![104, 1, 99]);
+		let error = machine.call(vec![], 2).unwrap_err();
+		assert!(matches!(error.error, IntcodeError::Halted { ip: 2, .. }));
+		assert_eq!(vec![1], error.outputs);
+		assert_eq!(
+			"halted at 2 before giving all the outputs (memory: [99, 0, 0, 0]) after 1 outputs",
+			error.to_string()
+		);
+
+		// A fault keeps the outputs before it too
+		let mut machine = Machine::new(vec![104, 1, 104, 2, 98]);
+		let error = machine.call(vec![], 3).unwrap_err();
+		assert!(matches!(error.error, IntcodeError::UnknownOpcode { ip: 4, .. }));
+		assert_eq!(vec![1, 2], error.outputs);
+		assert!(machine.output.is_empty());
+	}
+}
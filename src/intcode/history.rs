@@ -0,0 +1,230 @@
+//! Undo log of executed instructions, so a machine can run backwards.
+
+use super::{ExitCode, Instruction, IntcodeError, Machine, Operation};
+
+////////////////////////////////////////
+// HISTORY
+////////////////////////////////////////
+
+/// What it takes to undo one instruction.
+#[derive(Debug, PartialEq, Clone)]
+struct Undo {
+	ip: u128,
+	base: i128,
+	/// Address written and the value it held before.
+	write: Option<(u128, i128)>,
+	/// Whether that address was first used by this write.
+	first_use: bool,
+	input: Option<i128>,
+	/// Length of the output before the instruction printed.
+	output: Option<usize>,
+	executed: u64,
+	/// Length of the memory before, in case the write made it longer.
+	len: u128,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct History {
+	undo: Vec<Undo>,
+}
+
+impl History {
+	/// Number of instructions that can be undone.
+	pub fn len(&self) -> usize {
+		self.undo.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.undo.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.undo.clear();
+	}
+}
+
+////////////////////////////////////////
+// REVERSE EXECUTION
+////////////////////////////////////////
+
+impl Machine {
+	/// Like `step`, also logging in `history` how to undo the instruction.
+	pub fn step_recorded(&mut self, history: &mut History) -> Result<Option<ExitCode>, IntcodeError> {
+		let ip = self.ip;
+		let ins = Instruction::parse(ip, &self.memory)?;
+		let write = ins.opcode.output_parameter().and_then(|n| {
+			let i = ip + n as u128 + 1;
+			let address = self.memory.get_address(i, ins.parameter_modes[n]).ok()?;
+			Some((address, self.memory.cell(address)))
+		});
+		let undo = Undo {
+			ip,
+			base: self.memory.base,
+			first_use: write.is_some_and(|(address, _)| !self.memory.is_used(address)),
+			write,
+			input: self.input.front().copied().filter(|_| ins.opcode == Operation::Input),
+			output: Some(self.output.len()).filter(|_| ins.opcode == Operation::Output),
+			executed: self.executed,
+			len: self.memory.len,
+		};
+
+		let exit = self.step()?;
+		if exit != Some(ExitCode::Halt) && exit != Some(ExitCode::Wait) {
+			history.undo.push(undo);
+		}
+		Ok(exit)
+	}
+
+	/// Like `run`, also logging in `history` how to undo every instruction.
+	pub fn run_recorded(&mut self, history: &mut History) -> Result<ExitCode, IntcodeError> {
+		loop {
+			if let Some(exit) = self.step_recorded(history)? {
+				return Ok(exit);
+			}
+		}
+	}
+
+	fn undo(&mut self, undo: Undo) {
+		self.ip = undo.ip;
+		self.memory.base = undo.base;
+		if let Some((address, value)) = undo.write {
+			self.memory.set(address, value);
+			if undo.first_use {
+				self.memory.forget(address);
+			}
+		}
+		self.memory.len = undo.len;
+		self.executed = undo.executed;
+		if let Some(value) = undo.input {
+			self.input.push_front(value);
+		}
+		// Values the caller already took from the output stay gone
+		if let Some(len) = undo.output {
+			self.output.truncate(len);
+		}
+	}
+
+	/// Undoes the last `n` instructions in `history`, returning how many
+	/// there were to undo.
+	pub fn step_back(&mut self, history: &mut History, n: usize) -> usize {
+		let mut undone = 0;
+		while undone < n {
+			match history.undo.pop() {
+				Some(undo) => self.undo(undo),
+				None => break,
+			}
+			undone += 1;
+		}
+		undone
+	}
+
+	/// Runs backwards until the first undone instruction matching `stop`,
+	/// leaving the machine just before it. Returns its address, or `None`
+	/// and leaves the machine alone if `history` has no such instruction.
+	fn back_until(&mut self, history: &mut History, stop: impl Fn(&Undo) -> bool) -> Option<u128> {
+		let n = history.undo.iter().rev().position(stop)?;
+		self.step_back(history, n + 1);
+		Some(self.ip)
+	}
+
+	/// Runs backwards to just before the last instruction that wrote to
+	/// `address`.
+	pub fn back_to_write(&mut self, history: &mut History, address: u128) -> Option<u128> {
+		self.back_until(history, |undo| undo.write.map(|(a, _)| a) == Some(address))
+	}
+
+	/// Runs backwards to just before the last output, e.g. to see how a
+	/// failing self-test came up with its error code.
+	pub fn back_to_output(&mut self, history: &mut History) -> Option<u128> {
+		self.back_until(history, |undo| undo.output.is_some())
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::fixtures::adder;
+	use super::*;
+
+	#[test]
+	fn history_test1() {
+		let mut machine = Machine::new(adder());
+		machine.input.extend(vec![3, 4]);
+		let mut history = History::default();
+		machine.run_recorded(&mut history).unwrap();
+		machine.run_recorded(&mut history).unwrap();
+		assert_eq!((vec![3, 7], 9, 11), (machine.output.clone(), history.len(), machine.ip));
+
+		assert_eq!(3, machine.step_back(&mut history, 3));
+		assert_eq!((2, vec![3]), (machine.ip, machine.output.clone()));
+		assert!(machine.input.is_empty());
+		assert_eq!((4, 3), (machine.memory.cell(15), machine.memory.cell(16)));
+
+		assert_eq!(6, machine.step_back(&mut history, 10));
+		assert_eq!(machine.memory.to_vec(), adder());
+		assert_eq!(vec![3, 4], machine.input.iter().copied().collect::<Vec<_>>());
+		assert!(history.is_empty());
+
+		// The relative base comes back too
+		let mut machine = Machine::new(vec![109, 5, 99]);
+		machine.step_recorded(&mut history).unwrap();
+		assert_eq!(5, machine.memory.base);
+		machine.step_back(&mut history, 1);
+		assert_eq!((0, 0), (machine.ip, machine.memory.base));
+	}
+
+	#[test]
+	fn history_test2() {
+		let mut machine = Machine::new(adder());
+		machine.input.extend(vec![3, 4, 5]);
+		let mut history = History::default();
+		while machine.run_recorded(&mut history).unwrap() == ExitCode::Print {}
+
+		assert_eq!(Some(5), machine.back_to_write(&mut history, 16));
+		assert_eq!((5, 7), (machine.memory.cell(15), machine.memory.cell(16)));
+		assert_eq!(Some(9), machine.back_to_output(&mut history));
+		assert_eq!(vec![3], machine.output);
+		assert_eq!(None, machine.back_to_write(&mut history, 100));
+		assert_eq!(9, machine.ip);
+
+		machine.run_recorded(&mut history).unwrap();
+		assert_eq!(vec![3, 7], machine.output);
+	}
+
+	#[test]
+	fn history_test3() {
+		// Writing past the end, then undoing it
+		let mut machine = Machine::new(vec![1101, 1, 1, 100, 99]);
+		let mut history = History::default();
+		machine.step_recorded(&mut history).unwrap();
+		assert_eq!((1, 101), (machine.executed, machine.memory.len()));
+		assert_eq!(1, machine.step_back(&mut history, 1));
+		assert_eq!((0, 5), (machine.executed, machine.memory.len()));
+		assert_eq!(vec![1101, 1, 1, 100, 99], machine.memory.to_vec());
+	}
+
+	#[test]
+	fn history_test4() {
+		// Undoing the first write to a cell stops counting it
+		let mut machine = Machine::new(vec![1101, 1, 1, 100, 1101, 2, 2, 100, 99]);
+		machine.limits.cells = Some(10);
+		let mut history = History::default();
+		machine.step_recorded(&mut history).unwrap();
+		machine.step_recorded(&mut history).unwrap();
+		assert_eq!(10, machine.memory.cells());
+		assert_eq!(1, machine.step_back(&mut history, 1));
+		assert!(machine.memory.is_used(100));
+		assert_eq!((10, 2), (machine.memory.cells(), machine.memory.cell(100)));
+		assert_eq!(1, machine.step_back(&mut history, 1));
+		assert!(!machine.memory.is_used(100));
+		assert_eq!(9, machine.memory.cells());
+
+		// So running it again fits the same limit
+		machine.run_recorded(&mut history).unwrap();
+		assert_eq!((10, 4), (machine.memory.cells(), machine.memory.cell(100)));
+	}
+}
@@ -0,0 +1,435 @@
+//! Devices a machine can be wired to instead of driving its `input` and
+//! `output` queues by hand.
+
+use super::{ascii, ExitCode, IntcodeError, Machine};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+
+////////////////////////////////////////
+// DEVICE
+////////////////////////////////////////
+
+pub trait IoDevice {
+	/// Whether `read` has nothing to give right now. A machine asking for
+	/// input then stops with `ExitCode::Wait` instead of reading.
+	fn would_block(&mut self) -> bool;
+
+	fn read(&mut self) -> io::Result<i128>;
+
+	fn write(&mut self, value: i128) -> io::Result<()>;
+}
+
+impl Machine {
+	fn device_error(&self, error: io::Error) -> IntcodeError {
+		IntcodeError::Device {
+			ip: self.ip,
+			message: error.to_string(),
+			excerpt: self.memory.excerpt(self.ip),
+		}
+	}
+
+	/// Runs with inputs read from `device` when the program asks for them
+	/// and outputs written to it as they are produced. Values already in
+	/// `input` are consumed first. Stops when the program halts, or with
+	/// `ExitCode::Wait` when the device would block.
+	pub fn run_device(&mut self, device: &mut dyn IoDevice) -> Result<ExitCode, IntcodeError> {
+		loop {
+			match self.run()? {
+				ExitCode::Halt => return Ok(ExitCode::Halt),
+				ExitCode::Print => {
+					let values: Vec<i128> = self.output.drain(..).collect();
+					for value in values {
+						device.write(value).map_err(|e| self.device_error(e))?;
+					}
+				},
+				ExitCode::Wait => {
+					if device.would_block() {
+						return Ok(ExitCode::Wait);
+					}
+					let value = device.read().map_err(|e| self.device_error(e))?;
+					self.input.push_back(value);
+				},
+			}
+		}
+	}
+}
+
+fn blocked() -> io::Error {
+	io::Error::new(io::ErrorKind::WouldBlock, "no input available")
+}
+
+/// Turns the lines of a reader into values, a line at a time. A read or
+/// parse error is kept until `next` can report it.
+struct LineReader<R: BufRead> {
+	reader: R,
+	parse: fn(&str) -> io::Result<Vec<i128>>,
+	pending: VecDeque<i128>,
+	error: Option<io::Error>,
+}
+
+impl<R: BufRead> LineReader<R> {
+	fn new(reader: R, parse: fn(&str) -> io::Result<Vec<i128>>) -> Self {
+		Self {
+			reader,
+			parse,
+			pending: VecDeque::new(),
+			error: None,
+		}
+	}
+
+	/// Reads lines until there is a value, an error or the end of the
+	/// stream.
+	fn fill(&mut self) {
+		let mut line = String::new();
+		while self.pending.is_empty() && self.error.is_none() {
+			line.clear();
+			match self.reader.read_line(&mut line) {
+				Ok(0) => break,
+				Ok(_) => match (self.parse)(&line) {
+					Ok(values) => self.pending.extend(values),
+					Err(error) => self.error = Some(error),
+				},
+				Err(error) => self.error = Some(error),
+			}
+		}
+	}
+
+	/// Only the end of the stream blocks for good, errors are handed to
+	/// `next`.
+	fn would_block(&mut self) -> bool {
+		self.fill();
+		self.pending.is_empty() && self.error.is_none()
+	}
+
+	fn next(&mut self) -> io::Result<i128> {
+		self.fill();
+		if let Some(error) = self.error.take() {
+			return Err(error);
+		}
+		self.pending.pop_front().ok_or_else(blocked)
+	}
+}
+
+////////////////////////////////////////
+// QUEUE
+////////////////////////////////////////
+
+/// Plain in-memory queues.
+#[derive(Debug, Default, Clone)]
+pub struct Queue {
+	pub input: VecDeque<i128>,
+	pub output: Vec<i128>,
+}
+
+impl Queue {
+	pub fn new(input: Vec<i128>) -> Self {
+		Self {
+			input: input.into(),
+			output: Vec::new(),
+		}
+	}
+}
+
+impl IoDevice for Queue {
+	fn would_block(&mut self) -> bool {
+		self.input.is_empty()
+	}
+
+	fn read(&mut self) -> io::Result<i128> {
+		self.input.pop_front().ok_or_else(blocked)
+	}
+
+	fn write(&mut self, value: i128) -> io::Result<()> {
+		self.output.push(value);
+		Ok(())
+	}
+}
+
+////////////////////////////////////////
+// NUMBERS
+////////////////////////////////////////
+
+/// Integers in text form: inputs separated by whitespace or commas, one
+/// output per line.
+pub struct Numbers<R: BufRead, W: Write> {
+	reader: LineReader<R>,
+	writer: W,
+}
+
+fn parse_numbers(line: &str) -> io::Result<Vec<i128>> {
+	line.split(|c: char| c == ',' || c.is_whitespace())
+		.filter(|n| !n.is_empty())
+		.map(|n| {
+			n.parse()
+				.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("`{}` is not a number", n)))
+		})
+		.collect()
+}
+
+impl<R: BufRead, W: Write> Numbers<R, W> {
+	pub fn new(reader: R, writer: W) -> Self {
+		Self {
+			reader: LineReader::new(reader, parse_numbers),
+			writer,
+		}
+	}
+
+	pub fn into_writer(self) -> W {
+		self.writer
+	}
+}
+
+impl Numbers<BufReader<io::Stdin>, io::Stdout> {
+	pub fn stdio() -> Self {
+		Self::new(BufReader::new(io::stdin()), io::stdout())
+	}
+}
+
+impl Numbers<BufReader<File>, BufWriter<File>> {
+	/// Reads inputs from one file and writes outputs to another.
+	pub fn files<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<Self> {
+		Ok(Self::new(
+			BufReader::new(File::open(input)?),
+			BufWriter::new(File::create(output)?),
+		))
+	}
+}
+
+impl<R: BufRead, W: Write> IoDevice for Numbers<R, W> {
+	fn would_block(&mut self) -> bool {
+		self.reader.would_block()
+	}
+
+	fn read(&mut self) -> io::Result<i128> {
+		self.reader.next()
+	}
+
+	fn write(&mut self, value: i128) -> io::Result<()> {
+		writeln!(self.writer, "{}", value)?;
+		self.writer.flush()
+	}
+}
+
+////////////////////////////////////////
+// ASCII
+////////////////////////////////////////
+
+/// Text terminal: every line typed becomes its character codes followed by
+/// a newline (10). Outputs below 128 are printed as characters, anything
+/// larger as a number on its own line.
+pub struct Ascii<R: BufRead, W: Write> {
+	reader: LineReader<R>,
+	writer: W,
+}
+
+fn parse_text(line: &str) -> io::Result<Vec<i128>> {
+	ascii::encode(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl<R: BufRead, W: Write> Ascii<R, W> {
+	pub fn new(reader: R, writer: W) -> Self {
+		Self {
+			reader: LineReader::new(reader, parse_text),
+			writer,
+		}
+	}
+
+	pub fn into_writer(self) -> W {
+		self.writer
+	}
+}
+
+impl Ascii<BufReader<io::Stdin>, io::Stdout> {
+	pub fn terminal() -> Self {
+		Self::new(BufReader::new(io::stdin()), io::stdout())
+	}
+}
+
+impl<R: BufRead, W: Write> IoDevice for Ascii<R, W> {
+	fn would_block(&mut self) -> bool {
+		self.reader.would_block()
+	}
+
+	fn read(&mut self) -> io::Result<i128> {
+		self.reader.next()
+	}
+
+	fn write(&mut self, value: i128) -> io::Result<()> {
+		if (0..128).contains(&value) {
+			self.writer.write_all(&[value as u8])?;
+			if value == 10 {
+				self.writer.flush()?;
+			}
+			Ok(())
+		}
+		else {
+			writeln!(self.writer, "{}", value)?;
+			self.writer.flush()
+		}
+	}
+}
+
+////////////////////////////////////////
+// CALLBACK
+////////////////////////////////////////
+
+/// Closures: `read` returns `None` when it has nothing to give yet.
+pub struct Callback<R: FnMut() -> Option<i128>, W: FnMut(i128)> {
+	read: R,
+	write: W,
+	pending: Option<i128>,
+}
+
+impl<R: FnMut() -> Option<i128>, W: FnMut(i128)> Callback<R, W> {
+	pub fn new(read: R, write: W) -> Self {
+		Self {
+			read,
+			write,
+			pending: None,
+		}
+	}
+}
+
+impl<R: FnMut() -> Option<i128>, W: FnMut(i128)> IoDevice for Callback<R, W> {
+	fn would_block(&mut self) -> bool {
+		if self.pending.is_none() {
+			self.pending = (self.read)();
+		}
+		self.pending.is_none()
+	}
+
+	fn read(&mut self) -> io::Result<i128> {
+		self.pending.take().or_else(|| (self.read)()).ok_or_else(blocked)
+	}
+
+	fn write(&mut self, value: i128) -> io::Result<()> {
+		(self.write)(value);
+		Ok(())
+	}
+}
+
+////////////////////////////////////////
+// CHANNEL
+////////////////////////////////////////
+
+/// Both ends of `mpsc` channels, for machines running on their own thread.
+/// Reading blocks until a value arrives. A closed channel, usually because
+/// the machine on the other side stopped, is reported as a device error.
+pub struct Channel {
+	pub input: Receiver<i128>,
+	pub output: Sender<i128>,
+}
+
+impl IoDevice for Channel {
+	fn would_block(&mut self) -> bool {
+		false
+	}
+
+	fn read(&mut self) -> io::Result<i128> {
+		self.input
+			.recv()
+			.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "input channel closed"))
+	}
+
+	fn write(&mut self, value: i128) -> io::Result<()> {
+		self.output
+			.send(value)
+			.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output channel closed"))
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::fixtures::adder;
+	use super::*;
+	use std::fs;
+	use std::sync::mpsc;
+	use std::thread;
+
+	#[test]
+	fn io_test1() {
+		let mut machine = Machine::new(adder());
+		let mut queue = Queue::new(vec![1, 2]);
+		assert_eq!(Ok(ExitCode::Wait), machine.run_device(&mut queue));
+		queue.input.extend(vec![3, 0]);
+		assert_eq!(Ok(ExitCode::Halt), machine.run_device(&mut queue));
+		assert_eq!(vec![1, 3, 6], queue.output);
+		assert!(machine.output.is_empty());
+	}
+
+	#[test]
+	fn io_test2() {
+		let mut numbers = Numbers::new("1, 2\n\n3 0\n".as_bytes(), Vec::new());
+		let mut machine = Machine::new(adder());
+		assert_eq!(Ok(ExitCode::Halt), machine.run_device(&mut numbers));
+		assert_eq!("1\n3\n6\n", String::from_utf8(numbers.into_writer()).unwrap());
+
+		let mut numbers = Numbers::new("1 two".as_bytes(), Vec::new());
+		let error = Machine::new(adder()).run_device(&mut numbers).unwrap_err();
+		assert!(matches!(error, IntcodeError::Device { ip: 0, .. }));
+		assert!(error.to_string().contains("`two` is not a number"));
+	}
+
+	#[test]
+	fn io_test3() {
+		let mut ascii = Ascii::new("Hi\n".as_bytes(), Vec::new());
+		let code = vec![3, 100, 4, 100, 1005, 100, 0, 104, 1000, 99];
+		assert_eq!(Ok(ExitCode::Wait), Machine::new(code).run_device(&mut ascii));
+		assert_eq!("Hi\n", String::from_utf8(ascii.into_writer()).unwrap());
+
+		let mut ascii = Ascii::new("".as_bytes(), Vec::new());
+		let mut machine = Machine::new(vec![104, 79, 104, 75, 104, 10, 104, 1000, 99]);
+		assert_eq!(Ok(ExitCode::Halt), machine.run_device(&mut ascii));
+		assert_eq!("OK\n1000\n", String::from_utf8(ascii.into_writer()).unwrap());
+	}
+
+	#[test]
+	fn io_test4() {
+		let mut inputs = vec![0, 5, 4];
+		let mut outputs = Vec::new();
+		let mut callback = Callback::new(|| inputs.pop(), |value| outputs.push(value));
+		assert_eq!(Ok(ExitCode::Halt), Machine::new(adder()).run_device(&mut callback));
+		assert_eq!(vec![4, 9], outputs);
+	}
+
+	#[test]
+	fn io_test5() {
+		let dir = std::env::temp_dir();
+		let input = dir.join(format!("intcode-io-test5-{}.in", std::process::id()));
+		let output = dir.join(format!("intcode-io-test5-{}.out", std::process::id()));
+		fs::write(&input, "10\n20\n0\n").unwrap();
+		let mut files = Numbers::files(&input, &output).unwrap();
+		assert_eq!(Ok(ExitCode::Halt), Machine::new(adder()).run_device(&mut files));
+		drop(files);
+		assert_eq!("10\n30\n", fs::read_to_string(&output).unwrap());
+		fs::remove_file(input).unwrap();
+		fs::remove_file(output).unwrap();
+	}
+
+	#[test]
+	fn io_test6() {
+		let (to_machine, input) = mpsc::channel();
+		let (output, from_machine) = mpsc::channel();
+		let handle = thread::spawn(move || Machine::new(adder()).run_device(&mut Channel { input, output }));
+		to_machine.send(2).unwrap();
+		assert_eq!(Ok(2), from_machine.recv());
+		to_machine.send(0).unwrap();
+		assert_eq!(Ok(ExitCode::Halt), handle.join().unwrap());
+
+		let (to_machine, input) = mpsc::channel();
+		let (output, _) = mpsc::channel();
+		drop(to_machine);
+		let error = Machine::new(adder())
+			.run_device(&mut Channel { input, output })
+			.unwrap_err();
+		assert!(error.to_string().contains("input channel closed"));
+	}
+}
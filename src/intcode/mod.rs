@@ -0,0 +1,1078 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+pub mod ascii;
+pub mod assembler;
+pub mod bigint;
+pub mod cfg;
+pub mod debugger;
+pub mod disassembler;
+pub mod driver;
+pub mod history;
+pub mod io;
+pub mod peephole;
+pub mod profiler;
+pub mod search;
+pub mod snapshot;
+pub mod symbolic;
+pub mod topology;
+pub mod trace;
+pub mod transcript;
+pub mod translate;
+pub mod word;
+
+pub use word::{Arithmetic, Word};
+
+////////////////////////////////////////
+// OPERATION
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum Operation {
+	Add = 1,
+	Mul = 2,
+	Input = 3,
+	Output = 4,
+	JumpIfTrue = 5,
+	JumpIfFalse = 6,
+	LessThan = 7,
+	Equals = 8,
+	RelativeBase = 9,
+	Halt = 99,
+}
+
+impl Operation {
+	pub fn n_parameters(&self) -> usize {
+		match self {
+			Operation::Add => 3,
+			Operation::Mul => 3,
+			Operation::Input => 1,
+			Operation::Output => 1,
+			Operation::Halt => 0,
+			Operation::JumpIfTrue => 2,
+			Operation::JumpIfFalse => 2,
+			Operation::LessThan => 3,
+			Operation::RelativeBase => 1,
+			Operation::Equals => 3,
+		}
+	}
+
+	/// Index of the parameter the operation writes to, if any.
+	pub fn output_parameter(&self) -> Option<usize> {
+		match self {
+			Operation::Add | Operation::Mul | Operation::LessThan | Operation::Equals => Some(2),
+			Operation::Input => Some(0),
+			_ => None,
+		}
+	}
+
+	pub fn mnemonic(&self) -> &'static str {
+		match self {
+			Operation::Add => "add",
+			Operation::Mul => "mul",
+			Operation::Input => "in",
+			Operation::Output => "out",
+			Operation::JumpIfTrue => "jt",
+			Operation::JumpIfFalse => "jf",
+			Operation::LessThan => "lt",
+			Operation::Equals => "eq",
+			Operation::RelativeBase => "arb",
+			Operation::Halt => "hlt",
+		}
+	}
+
+	pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+		match mnemonic {
+			"add" => Some(Operation::Add),
+			"mul" => Some(Operation::Mul),
+			"in" => Some(Operation::Input),
+			"out" => Some(Operation::Output),
+			"jt" => Some(Operation::JumpIfTrue),
+			"jf" => Some(Operation::JumpIfFalse),
+			"lt" => Some(Operation::LessThan),
+			"eq" => Some(Operation::Equals),
+			"arb" => Some(Operation::RelativeBase),
+			"hlt" => Some(Operation::Halt),
+			_ => None,
+		}
+	}
+}
+
+impl TryFrom<i128> for Operation {
+	type Error = i128;
+
+	fn try_from(n: i128) -> Result<Self, Self::Error> {
+		match n {
+			1 => Ok(Operation::Add),
+			2 => Ok(Operation::Mul),
+			3 => Ok(Operation::Input),
+			4 => Ok(Operation::Output),
+			5 => Ok(Operation::JumpIfTrue),
+			6 => Ok(Operation::JumpIfFalse),
+			7 => Ok(Operation::LessThan),
+			8 => Ok(Operation::Equals),
+			9 => Ok(Operation::RelativeBase),
+			99 => Ok(Operation::Halt),
+			_ => Err(n),
+		}
+	}
+}
+
+////////////////////////////////////////
+// PARAMETER MODE
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ParameterMode {
+	Position = 0,
+	Immediate = 1,
+	Relative = 2,
+}
+
+impl TryFrom<i128> for ParameterMode {
+	type Error = i128;
+
+	fn try_from(n: i128) -> Result<Self, Self::Error> {
+		match n {
+			0 => Ok(ParameterMode::Position),
+			1 => Ok(ParameterMode::Immediate),
+			2 => Ok(ParameterMode::Relative),
+			_ => Err(n),
+		}
+	}
+}
+
+////////////////////////////////////////
+// ERROR
+////////////////////////////////////////
+
+/// Everything that can go wrong while running a program. Each fault records
+/// the instruction pointer of the failing instruction and the words stored
+/// from there on (see `Memory::excerpt`).
+#[derive(Debug, PartialEq, Clone)]
+pub enum IntcodeError<W = i128> {
+	UnknownOpcode {
+		ip: u128,
+		value: W,
+		excerpt: Vec<W>,
+	},
+	BadParameterMode {
+		ip: u128,
+		value: W,
+		mode: i128,
+		excerpt: Vec<W>,
+	},
+	NegativeAddress {
+		ip: u128,
+		address: W,
+		excerpt: Vec<W>,
+	},
+	WriteInImmediateMode {
+		ip: u128,
+		value: W,
+		excerpt: Vec<W>,
+	},
+	InputExhausted {
+		ip: u128,
+		excerpt: Vec<W>,
+	},
+	/// The program halted before giving all the outputs it was asked for.
+	Halted {
+		ip: u128,
+		excerpt: Vec<W>,
+	},
+	/// The machine ran into one of its `Limits`; the instruction at `ip` has
+	/// not been executed.
+	LimitExceeded {
+		ip: u128,
+		limit: Limit,
+		excerpt: Vec<W>,
+	},
+	/// Only raised under `Arithmetic::Checked`.
+	Overflow {
+		ip: u128,
+		operation: Operation,
+		operands: [W; 2],
+		excerpt: Vec<W>,
+	},
+	/// An `io::IoDevice` failed to provide or accept a value.
+	Device {
+		ip: u128,
+		message: String,
+		excerpt: Vec<W>,
+	},
+}
+
+impl<W> IntcodeError<W> {
+	pub fn ip(&self) -> u128 {
+		match self {
+			IntcodeError::UnknownOpcode { ip, .. }
+			| IntcodeError::BadParameterMode { ip, .. }
+			| IntcodeError::NegativeAddress { ip, .. }
+			| IntcodeError::WriteInImmediateMode { ip, .. }
+			| IntcodeError::InputExhausted { ip, .. }
+			| IntcodeError::Halted { ip, .. }
+			| IntcodeError::LimitExceeded { ip, .. }
+			| IntcodeError::Overflow { ip, .. }
+			| IntcodeError::Device { ip, .. } => *ip,
+		}
+	}
+
+	pub fn excerpt(&self) -> &[W] {
+		match self {
+			IntcodeError::UnknownOpcode { excerpt, .. }
+			| IntcodeError::BadParameterMode { excerpt, .. }
+			| IntcodeError::NegativeAddress { excerpt, .. }
+			| IntcodeError::WriteInImmediateMode { excerpt, .. }
+			| IntcodeError::InputExhausted { excerpt, .. }
+			| IntcodeError::Halted { excerpt, .. }
+			| IntcodeError::LimitExceeded { excerpt, .. }
+			| IntcodeError::Overflow { excerpt, .. }
+			| IntcodeError::Device { excerpt, .. } => excerpt,
+		}
+	}
+}
+
+impl<W: Word> fmt::Display for IntcodeError<W> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			IntcodeError::UnknownOpcode { ip, value, .. } => write!(f, "unknown opcode {} at {}", value, ip)?,
+			IntcodeError::BadParameterMode { ip, value, mode, .. } => {
+				write!(f, "bad parameter mode {} in instruction {} at {}", mode, value, ip)?
+			},
+			IntcodeError::NegativeAddress { ip, address, .. } => {
+				write!(f, "negative address {} used by instruction at {}", address, ip)?
+			},
+			IntcodeError::WriteInImmediateMode { ip, value, .. } => {
+				write!(f, "instruction {} at {} writes in immediate mode", value, ip)?
+			},
+			IntcodeError::InputExhausted { ip, .. } => write!(f, "no input left for instruction at {}", ip)?,
+			IntcodeError::Halted { ip, .. } => write!(f, "halted at {} before giving all the outputs", ip)?,
+			IntcodeError::LimitExceeded { ip, limit, .. } => match limit {
+				Limit::Instructions(n) => write!(f, "limit of {} instructions reached at {}", n, ip)?,
+				Limit::Cells(n) => write!(f, "instruction at {} would use more than {} memory cells", ip, n)?,
+				Limit::Deadline => write!(f, "deadline passed at {}", ip)?,
+			},
+			IntcodeError::Overflow {
+				ip,
+				operation,
+				operands: [a, b],
+				..
+			} => write!(f, "{} {}, {} at {} overflows", operation.mnemonic(), a, b, ip)?,
+			IntcodeError::Device { ip, message, .. } => write!(f, "device error at {}: {}", ip, message)?,
+		}
+		write!(f, " (memory: {:?})", self.excerpt())
+	}
+}
+
+impl<W: Word> Error for IntcodeError<W> {}
+
+////////////////////////////////////////
+// MEMORY
+////////////////////////////////////////
+
+/// Cells below `DENSE_LIMIT` live in one vector that grows as needed, so the
+/// program and anything written close to it is a plain index away. Cells
+/// further out are kept in pages of `PAGE_SIZE` allocated on first write.
+const DENSE_LIMIT: u128 = 1 << 16;
+const PAGE_SIZE: u128 = 1 << 10;
+
+/// The highest address a program can use, so that going past an address,
+/// or past the parameters of an instruction there, never overflows.
+pub const MAX_ADDRESS: u128 = i128::MAX as u128;
+
+#[derive(Clone)]
+pub struct Memory<W = i128> {
+	pub base: W,
+	dense: Vec<W>,
+	/// One bit per cell of `dense`, set once it has been loaded or written.
+	dense_used: Vec<u64>,
+	pages: HashMap<u128, Page<W>>,
+	len: u128,
+	/// Cells loaded or written so far, each counted once.
+	used: u128,
+	/// Tells memories apart, see `version`.
+	id: u64,
+	writes: u64,
+}
+
+#[derive(Clone)]
+struct Page<W> {
+	cells: Box<[W]>,
+	used: [u64; PAGE_SIZE as usize / 64],
+}
+
+/// Sets bit `n`, telling whether it was clear before.
+fn mark(bits: &mut [u64], n: usize) -> bool {
+	let (word, bit) = (n / 64, 1 << (n % 64));
+	let new = bits[word] & bit == 0;
+	bits[word] |= bit;
+	new
+}
+
+/// Clears bit `n`, telling whether it was set before.
+fn unmark(bits: &mut [u64], n: usize) -> bool {
+	let (word, bit) = (n / 64, 1 << (n % 64));
+	let old = bits.get(word).is_some_and(|w| w & bit != 0);
+	if old {
+		bits[word] &= !bit;
+	}
+	old
+}
+
+fn is_marked(bits: &[u64], n: usize) -> bool {
+	bits.get(n / 64).is_some_and(|word| word & (1 << (n % 64)) != 0)
+}
+
+static MEMORIES: AtomicU64 = AtomicU64::new(0);
+
+impl Memory {
+	pub fn new(code: Vec<i128>) -> Self {
+		Memory::from_code(code)
+	}
+}
+
+impl<W: Word> Memory<W> {
+	pub fn from_code(code: Vec<W>) -> Self {
+		let mut dense_used = vec![0; code.len().div_ceil(64)];
+		for n in 0..code.len() {
+			mark(&mut dense_used, n);
+		}
+		Self {
+			base: W::default(),
+			len: code.len() as u128,
+			used: code.len() as u128,
+			dense: code,
+			dense_used,
+			pages: HashMap::new(),
+			id: MEMORIES.fetch_add(1, Ordering::Relaxed),
+			writes: 0,
+		}
+	}
+
+	/// The value stored at `address`; cells never written hold 0.
+	#[inline]
+	pub fn cell(&self, address: u128) -> W {
+		if address < self.dense.len() as u128 {
+			return self.dense[address as usize].clone();
+		}
+		match self.pages.get(&(address / PAGE_SIZE)) {
+			Some(page) => page.cells[(address % PAGE_SIZE) as usize].clone(),
+			None => W::default(),
+		}
+	}
+
+	pub fn set(&mut self, address: u128, value: W) {
+		self.len = self.len.max(address.saturating_add(1));
+		self.writes += 1;
+		if address < DENSE_LIMIT {
+			let address = address as usize;
+			if address >= self.dense.len() {
+				self.dense.resize(address + 1, W::default());
+				self.dense_used.resize(self.dense.len().div_ceil(64), 0);
+			}
+			self.dense[address] = value;
+			self.used += mark(&mut self.dense_used, address) as u128;
+		}
+		else {
+			let page = self.pages.entry(address / PAGE_SIZE).or_insert_with(|| Page {
+				cells: vec![W::default(); PAGE_SIZE as usize].into_boxed_slice(),
+				used: [0; PAGE_SIZE as usize / 64],
+			});
+			let n = (address % PAGE_SIZE) as usize;
+			page.cells[n] = value;
+			self.used += mark(&mut page.used, n) as u128;
+		}
+	}
+
+	/// The value of the parameter at `i`, read in `mode`.
+	pub fn get(&self, i: u128, mode: ParameterMode) -> Result<W, W> {
+		let address = self.get_address(i, mode)?;
+		Ok(self.cell(address))
+	}
+
+	/// Where the parameter at `i` points in `mode`, or `i` itself for an
+	/// immediate one. Fails with the value found there if it is not an
+	/// address, see `Word::to_address`.
+	pub fn get_address(&self, i: u128, mode: ParameterMode) -> Result<u128, W> {
+		let address = match mode {
+			ParameterMode::Immediate => return Ok(i),
+			ParameterMode::Position => self.cell(i),
+			ParameterMode::Relative => self.cell(i).plus(&self.base),
+		};
+		address.to_address().ok_or(address)
+	}
+
+	pub fn add_to_base(&mut self, increment: W) {
+		self.base = self.base.plus(&increment);
+	}
+
+	/// Stores `value` where the parameter at `i` points in `mode`.
+	pub fn write(&mut self, i: u128, value: W, mode: ParameterMode) -> Result<(), W> {
+		let address = self.get_address(i, mode)?;
+		self.set(address, value);
+		Ok(())
+	}
+
+	/// The longest instruction there is, starting at `ip`.
+	pub fn excerpt(&self, ip: u128) -> Vec<W> {
+		(ip..ip.saturating_add(4)).map(|i| self.cell(i)).collect()
+	}
+
+	/// How many distinct cells were loaded with the program or written
+	/// since. Cells only ever read are not counted, however far out.
+	pub fn cells(&self) -> u128 {
+		self.used
+	}
+
+	/// Whether `address` is one of the cells counted in `cells`.
+	pub fn is_used(&self, address: u128) -> bool {
+		if address < DENSE_LIMIT {
+			is_marked(&self.dense_used, address as usize)
+		}
+		else {
+			self.pages
+				.get(&(address / PAGE_SIZE))
+				.is_some_and(|page| is_marked(&page.used, (address % PAGE_SIZE) as usize))
+		}
+	}
+
+	/// Stops counting `address` in `cells`, leaving its value alone. For
+	/// undoing the write that first used it.
+	fn forget(&mut self, address: u128) {
+		let forgotten = if address < DENSE_LIMIT {
+			unmark(&mut self.dense_used, address as usize)
+		}
+		else {
+			self.pages
+				.get_mut(&(address / PAGE_SIZE))
+				.is_some_and(|page| unmark(&mut page.used, (address % PAGE_SIZE) as usize))
+		};
+		self.used -= forgotten as u128;
+	}
+
+	/// Changes with every write, and from one memory to another, so that
+	/// whatever was worked out from the contents can be checked to still hold.
+	/// Clones start with the version of the original.
+	pub fn version(&self) -> (u64, u64) {
+		(self.id, self.writes)
+	}
+
+	/// One past the highest address loaded or written.
+	pub fn len(&self) -> u128 {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Every cell from address 0 up to the highest one ever touched.
+	pub fn to_vec(&self) -> Vec<W> {
+		(0..self.len).map(|i| self.cell(i)).collect()
+	}
+}
+
+////////////////////////////////////////
+// INSTRUCTION
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Instruction {
+	pub opcode: Operation,
+	/// Only the first `opcode.n_parameters()` modes are meaningful.
+	pub parameter_modes: [ParameterMode; 3],
+}
+
+impl Instruction {
+	/// Decodes the instruction stored at `ip`: the two lowest digits are the
+	/// opcode, followed by one digit per parameter mode.
+	pub fn parse<W: Word>(ip: u128, memory: &Memory<W>) -> Result<Self, IntcodeError<W>> {
+		let opcode = memory.cell(ip);
+		let unknown = || IntcodeError::UnknownOpcode {
+			ip,
+			value: opcode.clone(),
+			excerpt: memory.excerpt(ip),
+		};
+		let n = opcode.to_i64().ok_or_else(unknown)? as i128;
+		let operation = Operation::try_from(n % 100).map_err(|_| unknown())?;
+		let mut parameter_modes = [ParameterMode::Position; 3];
+		let mut digits = n / 100;
+		for mode in parameter_modes.iter_mut().take(operation.n_parameters()) {
+			*mode = ParameterMode::try_from(digits % 10).map_err(|mode| IntcodeError::BadParameterMode {
+				ip,
+				value: opcode.clone(),
+				mode,
+				excerpt: memory.excerpt(ip),
+			})?;
+			digits /= 10;
+		}
+		Ok(Self {
+			opcode: operation,
+			parameter_modes,
+		})
+	}
+
+	pub fn modes(&self) -> &[ParameterMode] {
+		&self.parameter_modes[..self.opcode.n_parameters()]
+	}
+
+	fn get<W: Word>(&self, n: usize, ip: u128, memory: &Memory<W>) -> Result<W, IntcodeError<W>> {
+		memory
+			.get(ip + n as u128 + 1, self.parameter_modes[n])
+			.map_err(|address| IntcodeError::NegativeAddress {
+				ip,
+				address,
+				excerpt: memory.excerpt(ip),
+			})
+	}
+
+	fn write<W: Word>(&self, n: usize, ip: u128, value: W, memory: &mut Memory<W>) -> Result<(), IntcodeError<W>> {
+		if self.parameter_modes[n] == ParameterMode::Immediate {
+			return Err(IntcodeError::WriteInImmediateMode {
+				ip,
+				value: memory.cell(ip),
+				excerpt: memory.excerpt(ip),
+			});
+		}
+		match memory.write(ip + n as u128 + 1, value, self.parameter_modes[n]) {
+			Ok(()) => Ok(()),
+			Err(address) => Err(IntcodeError::NegativeAddress {
+				ip,
+				address,
+				excerpt: memory.excerpt(ip),
+			}),
+		}
+	}
+
+	fn jump<W: Word>(&self, ip: u128, memory: &Memory<W>) -> Result<u128, IntcodeError<W>> {
+		let target = self.get(1, ip, memory)?;
+		target.to_address().ok_or_else(|| IntcodeError::NegativeAddress {
+			ip,
+			address: target,
+			excerpt: memory.excerpt(ip),
+		})
+	}
+
+	pub fn eval<W: Word>(
+		&self,
+		i: &mut u128,
+		memory: &mut Memory<W>,
+		input: &mut VecDeque<W>,
+		output: &mut Vec<W>,
+		arithmetic: Arithmetic,
+	) -> Result<(), IntcodeError<W>> {
+		let ip = *i;
+		let mut new_i = ip + self.opcode.n_parameters() as u128 + 1;
+		match self.opcode {
+			Operation::Add | Operation::Mul => {
+				let x1 = self.get(0, ip, memory)?;
+				let x2 = self.get(1, ip, memory)?;
+				let result = match self.opcode {
+					Operation::Add => arithmetic.add(&x1, &x2),
+					_ => arithmetic.mul(&x1, &x2),
+				};
+				let value = result.ok_or_else(|| IntcodeError::Overflow {
+					ip,
+					operation: self.opcode,
+					operands: [x1, x2],
+					excerpt: memory.excerpt(ip),
+				})?;
+				self.write(2, ip, value, memory)?;
+			},
+			Operation::Input => {
+				let value = input.pop_front().ok_or_else(|| IntcodeError::InputExhausted {
+					ip,
+					excerpt: memory.excerpt(ip),
+				})?;
+				self.write(0, ip, value, memory)?;
+			},
+			Operation::Output => {
+				output.push(self.get(0, ip, memory)?);
+			},
+			Operation::JumpIfTrue => {
+				if !self.get(0, ip, memory)?.is_zero() {
+					new_i = self.jump(ip, memory)?;
+				}
+			},
+			Operation::JumpIfFalse => {
+				if self.get(0, ip, memory)?.is_zero() {
+					new_i = self.jump(ip, memory)?;
+				}
+			},
+			Operation::LessThan => {
+				let x1 = self.get(0, ip, memory)?;
+				let x2 = self.get(1, ip, memory)?;
+				self.write(2, ip, W::from_i64(if x1 < x2 { 1 } else { 0 }), memory)?;
+			},
+			Operation::Equals => {
+				let x1 = self.get(0, ip, memory)?;
+				let x2 = self.get(1, ip, memory)?;
+				self.write(2, ip, W::from_i64(if x1 == x2 { 1 } else { 0 }), memory)?;
+			},
+			Operation::RelativeBase => {
+				let increment = self.get(0, ip, memory)?;
+				memory.add_to_base(increment);
+			},
+			Operation::Halt => {},
+		}
+		*i = new_i;
+		Ok(())
+	}
+}
+
+////////////////////////////////////////
+// MACHINE
+////////////////////////////////////////
+
+/// Resources a machine may use before it is stopped with
+/// `IntcodeError::LimitExceeded`. Nothing is limited by default.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Limits {
+	/// Instructions executed, as counted in `Machine::executed`.
+	pub instructions: Option<u64>,
+	/// See `Memory::cells`.
+	pub cells: Option<u128>,
+	/// Only checked every `DEADLINE_INTERVAL` instructions.
+	pub deadline: Option<Instant>,
+}
+
+/// Which of the `Limits` was hit.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Limit {
+	Instructions(u64),
+	Cells(u128),
+	Deadline,
+}
+
+/// Reading the clock is slow next to running an instruction.
+pub const DEADLINE_INTERVAL: u64 = 1 << 10;
+
+#[derive(Debug, PartialEq)]
+pub enum ExitCode {
+	Halt,
+	Wait,
+	Print,
+}
+
+#[derive(Clone)]
+pub struct Machine<W = i128> {
+	pub ip: u128,
+	pub memory: Memory<W>,
+	pub input: VecDeque<W>,
+	pub output: Vec<W>,
+	pub arithmetic: Arithmetic,
+	pub limits: Limits,
+	/// Instructions executed so far.
+	pub executed: u64,
+	/// Runs through `peephole`. Ignored while any of the `limits` is set,
+	/// as they are checked one instruction at a time.
+	pub optimize: bool,
+	peephole: peephole::Peephole<W>,
+	/// Instructions decoded so far, next to the word they were decoded from.
+	/// An entry is only used while memory still holds that word, so code
+	/// that modifies itself gets decoded again.
+	decoded: Vec<Option<(W, Instruction)>>,
+}
+
+impl Machine {
+	pub fn new(code: Vec<i128>) -> Self {
+		Machine::from_code(code)
+	}
+}
+
+impl<W: Word> Machine<W> {
+	/// A machine with words of any size, e.g. `Machine::<BigInt>::from_code`.
+	pub fn from_code(code: Vec<W>) -> Self {
+		Machine {
+			ip: 0,
+			memory: Memory::from_code(code),
+			input: VecDeque::new(),
+			output: Vec::new(),
+			arithmetic: Arithmetic::default(),
+			limits: Limits::default(),
+			executed: 0,
+			optimize: false,
+			peephole: peephole::Peephole::default(),
+			decoded: Vec::new(),
+		}
+	}
+
+	fn fetch(&mut self) -> Result<Instruction, IntcodeError<W>> {
+		if self.ip >= DENSE_LIMIT {
+			return Instruction::parse(self.ip, &self.memory);
+		}
+		let word = self.memory.cell(self.ip);
+		let slot = self.ip as usize;
+		if let Some(Some((decoded_word, ins))) = self.decoded.get(slot) {
+			if *decoded_word == word {
+				return Ok(*ins);
+			}
+		}
+		let ins = Instruction::parse(self.ip, &self.memory)?;
+		if slot >= self.decoded.len() {
+			self.decoded.resize(slot + 1, None);
+		}
+		self.decoded[slot] = Some((word, ins));
+		Ok(ins)
+	}
+
+	/// Executes a single instruction. Returns the reason to stop, if there
+	/// is one: `Halt` and `Wait` leave the instruction pointer untouched,
+	/// `Print` comes right after the output has been pushed.
+	pub fn step(&mut self) -> Result<Option<ExitCode>, IntcodeError<W>> {
+		let ins = self.fetch()?;
+		if ins.opcode == Operation::Halt {
+			return Ok(Some(ExitCode::Halt));
+		}
+		if ins.opcode == Operation::Input && self.input.is_empty() {
+			return Ok(Some(ExitCode::Wait));
+		}
+		self.check_limits(&ins)?;
+		ins.eval(
+			&mut self.ip,
+			&mut self.memory,
+			&mut self.input,
+			&mut self.output,
+			self.arithmetic,
+		)?;
+		self.executed += 1;
+		if ins.opcode == Operation::Output {
+			return Ok(Some(ExitCode::Print));
+		}
+		Ok(None)
+	}
+
+	fn check_limits(&self, ins: &Instruction) -> Result<(), IntcodeError<W>> {
+		let limits = &self.limits;
+		let mut exceeded = None;
+		if limits.instructions.is_some_and(|n| self.executed >= n) {
+			exceeded = limits.instructions.map(Limit::Instructions);
+		}
+		if let (Some(n), Some(parameter)) = (limits.cells, ins.opcode.output_parameter()) {
+			let address = self
+				.memory
+				.get_address(self.ip + parameter as u128 + 1, ins.parameter_modes[parameter]);
+			let new = address.is_ok_and(|a| !self.memory.is_used(a));
+			if self.memory.cells() + new as u128 > n {
+				exceeded = Some(Limit::Cells(n));
+			}
+		}
+		if self.executed.is_multiple_of(DEADLINE_INTERVAL) && limits.deadline.is_some_and(|d| Instant::now() >= d) {
+			exceeded = Some(Limit::Deadline);
+		}
+		match exceeded {
+			Some(limit) => Err(IntcodeError::LimitExceeded {
+				ip: self.ip,
+				limit,
+				excerpt: self.memory.excerpt(self.ip),
+			}),
+			None => Ok(()),
+		}
+	}
+
+	/// Runs until the program halts, needs an input that is not there yet,
+	/// or has just produced an output. See `optimize` for when it is used.
+	pub fn run(&mut self) -> Result<ExitCode, IntcodeError<W>> {
+		if self.optimize && self.limits == Limits::default() {
+			return self.run_optimized();
+		}
+		self.interpret()
+	}
+
+	fn interpret(&mut self) -> Result<ExitCode, IntcodeError<W>> {
+		loop {
+			if let Some(exit) = self.step()? {
+				return Ok(exit);
+			}
+		}
+	}
+
+	/// Waiting for an input that will never come is reported as
+	/// `IntcodeError::InputExhausted`.
+	pub fn run_until_halt(&mut self) -> Result<(), IntcodeError<W>> {
+		loop {
+			match self.run()? {
+				ExitCode::Halt => return Ok(()),
+				ExitCode::Wait => {
+					return Err(IntcodeError::InputExhausted {
+						ip: self.ip,
+						excerpt: self.memory.excerpt(self.ip),
+					})
+				},
+				ExitCode::Print => {},
+			}
+		}
+	}
+}
+
+/// Parses the comma separated programs the puzzles come with.
+pub fn parse_program(text: &str) -> Result<Vec<i128>, ParseIntError> {
+	parse_words(text)
+}
+
+/// Like `parse_program`, for words of any size.
+pub fn parse_words<W: Word>(text: &str) -> Result<Vec<W>, <W as FromStr>::Err> {
+	text.trim().split(',').map(|n| n.trim().parse()).collect()
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+/// Programs the tests of more than one module run.
+#[cfg(test)]
+mod fixtures {
+
+	/// Adds up the numbers it reads until it reads a 0, printing the
+	/// running total each time.
+	pub fn adder() -> Vec<i128> {
+		vec![3, 15, 1006, 15, 14, 1, 15, 16, 16, 4, 16, 1105, 1, 0, 99, 0, 0]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn intcode_test1() {
+		let mut machine = Machine::new(vec![3, 7, 4, 7, 4, 7, 99, 0]);
+		assert_eq!(Ok(ExitCode::Wait), machine.run());
+		machine.input.push_back(42);
+		assert_eq!(Ok(ExitCode::Print), machine.run());
+		assert_eq!(Ok(ExitCode::Print), machine.run());
+		assert_eq!(Ok(ExitCode::Halt), machine.run());
+		assert_eq!(vec![42, 42], machine.output);
+	}
+
+	#[test]
+	fn intcode_test2() {
+		let mut machine = Machine::new(vec![1101, 2, 3, 10, 99]);
+		machine.run_until_halt().unwrap();
+		assert_eq!(vec![1101, 2, 3, 10, 99, 0, 0, 0, 0, 0, 5], machine.memory.to_vec());
+	}
+
+	#[test]
+	fn intcode_test3() {
+		let mut machine = Machine::new(vec![1101, 2, 3, 5, 42, 0, 7]);
+		let error = IntcodeError::UnknownOpcode {
+			ip: 4,
+			value: 42,
+			excerpt: vec![42, 5, 7, 0],
+		};
+		assert_eq!(Err(error), machine.run());
+	}
+
+	#[test]
+	fn intcode_test4() {
+		let mut machine = Machine::new(vec![301, 0, 0, 0, 99]);
+		assert_eq!(
+			Err(IntcodeError::BadParameterMode {
+				ip: 0,
+				value: 301,
+				mode: 3,
+				excerpt: vec![301, 0, 0, 0],
+			}),
+			machine.run()
+		);
+	}
+
+	#[test]
+	fn intcode_test5() {
+		let mut machine = Machine::new(vec![4, -1, 99]);
+		assert_eq!(
+			Err(IntcodeError::NegativeAddress {
+				ip: 0,
+				address: -1,
+				excerpt: vec![4, -1, 99, 0],
+			}),
+			machine.run()
+		);
+	}
+
+	#[test]
+	fn intcode_test6() {
+		let mut machine = Machine::new(vec![11101, 1, 1, 0, 99]);
+		let error = machine.run().unwrap_err();
+		assert_eq!(0, error.ip());
+		assert!(matches!(error, IntcodeError::WriteInImmediateMode { value: 11101, .. }));
+	}
+
+	#[test]
+	fn intcode_test7() {
+		let mut machine = Machine::new(vec![3, 0, 99]);
+		assert_eq!(
+			Err(IntcodeError::InputExhausted {
+				ip: 0,
+				excerpt: vec![3, 0, 99, 0],
+			}),
+			machine.run_until_halt()
+		);
+		let mut memory = Memory::new(vec![3, 0, 99]);
+		let ins = Instruction::parse(0, &memory).unwrap();
+		let result = ins.eval(
+			&mut 0,
+			&mut memory,
+			&mut VecDeque::new(),
+			&mut Vec::new(),
+			Arithmetic::default(),
+		);
+		assert!(matches!(result, Err(IntcodeError::InputExhausted { ip: 0, .. })));
+	}
+
+	#[test]
+	fn intcode_test8() {
+		let mut memory = Memory::new(vec![1, 2, 3]);
+		memory.set(5, 6);
+		memory.set(1 << 40, 7);
+		memory.set((1 << 40) + 1, 8);
+		assert_eq!(6, memory.cell(5));
+		assert_eq!(0, memory.cell(4));
+		assert_eq!(7, memory.cell(1 << 40));
+		assert_eq!(8, memory.get((1 << 40) + 1, ParameterMode::Immediate).unwrap());
+		assert_eq!(0, memory.cell((1 << 40) + 2));
+		assert_eq!((1 << 40) + 2, memory.len());
+		assert_eq!(vec![1, 2, 3], Memory::new(vec![1, 2, 3]).to_vec());
+	}
+
+	#[test]
+	fn intcode_test9() {
+		let mut machine = Machine::new(vec![109, 1 << 50, 21101, 4, 5, 3, 204, 3, 99]);
+		machine.run_until_halt().unwrap();
+		assert_eq!(vec![9], machine.output);
+	}
+
+	#[test]
+	fn intcode_test10() {
+		let source = "
+			start:  out #7
+			        jt [flag], #end
+			        add #1, #0, [flag]
+			        add #4, #0, [start]  ; out #7 becomes out [7]
+			        jf #0, #start
+			end:    hlt
+			flag:   .data 0
+		";
+		let mut machine = Machine::new(assembler::assemble(source).unwrap());
+		machine.run_until_halt().unwrap();
+		assert_eq!(vec![7, 0], machine.output);
+	}
+
+	#[test]
+	fn intcode_test11() {
+		// [9] = [7] * [8], printed
+		let code = vec![2, 7, 8, 9, 4, 9, 99, i64::MAX, 2, 0];
+		let run = |arithmetic| {
+			let mut machine = Machine::<i64>::from_code(code.clone());
+			machine.arithmetic = arithmetic;
+			machine.run_until_halt().map(|_| machine.output)
+		};
+		assert_eq!(Ok(vec![-2]), run(Arithmetic::Wrapping));
+		assert_eq!(Ok(vec![i64::MAX]), run(Arithmetic::Saturating));
+		let error = run(Arithmetic::Checked).unwrap_err();
+		assert_eq!(
+			IntcodeError::Overflow {
+				ip: 0,
+				operation: Operation::Mul,
+				operands: [i64::MAX, 2],
+				excerpt: vec![2, 7, 8, 9],
+			},
+			error
+		);
+		assert_eq!(
+			"mul 9223372036854775807, 2 at 0 overflows (memory: [2, 7, 8, 9])",
+			error.to_string()
+		);
+	}
+
+	#[test]
+	fn intcode_test12() {
+		// Loops forever
+		let mut machine = Machine::new(vec![1105, 1, 0]);
+		machine.limits.instructions = Some(1000);
+		let error = machine.run_until_halt().unwrap_err();
+		assert_eq!(
+			IntcodeError::LimitExceeded {
+				ip: 0,
+				limit: Limit::Instructions(1000),
+				excerpt: vec![1105, 1, 0, 0],
+			},
+			error
+		);
+		assert_eq!(
+			"limit of 1000 instructions reached at 0 (memory: [1105, 1, 0, 0])",
+			error.to_string()
+		);
+		assert_eq!(1000, machine.executed);
+		machine.limits.instructions = Some(1500);
+		assert!(machine.run_until_halt().is_err());
+		assert_eq!(1500, machine.executed);
+
+		machine.limits = Limits {
+			deadline: Some(Instant::now()),
+			..Limits::default()
+		};
+		let error = machine.run_until_halt().unwrap_err();
+		assert!(matches!(
+			error,
+			IntcodeError::LimitExceeded {
+				limit: Limit::Deadline,
+				..
+			}
+		));
+		assert!(machine.executed.is_multiple_of(DEADLINE_INTERVAL));
+	}
+
+	#[test]
+	fn intcode_test13() {
+		// Writes to [100], [101] and then further out every time round
+		let code = vec![109, 100, 21101, 1, 0, 0, 109, 1, 1105, 1, 2];
+		let mut machine = Machine::new(code.clone());
+		machine.limits.cells = Some(110);
+		let error = machine.run_until_halt().unwrap_err();
+		assert!(matches!(
+			error,
+			IntcodeError::LimitExceeded {
+				ip: 2,
+				limit: Limit::Cells(110),
+				..
+			}
+		));
+		assert_eq!(110, machine.memory.cells());
+		assert_eq!(199, machine.memory.base);
+
+		// Cells far away, or written twice, count once
+		let code = vec![1101, 0, 0, 1 << 40, 1101, 0, 0, 1 << 40, 1101, 0, 0, 200, 99];
+		let mut machine = Machine::new(code.clone());
+		machine.limits.cells = Some(15);
+		machine.run_until_halt().unwrap();
+		assert_eq!(15, machine.memory.cells());
+		assert!(machine.memory.is_used(1 << 40) && !machine.memory.is_used((1 << 40) + 1));
+		assert!(machine.memory.is_used(200) && !machine.memory.is_used(199));
+		let mut machine = Machine::new(code);
+		machine.limits.cells = Some(14);
+		assert!(machine.run_until_halt().is_err());
+		assert_eq!(8, machine.ip);
+	}
+
+	#[test]
+	fn intcode_test14() {
+		let mut memory = Memory::new(vec![99]);
+		memory.set(u128::MAX, 7);
+		assert_eq!(7, memory.cell(u128::MAX));
+		assert_eq!(u128::MAX, memory.len());
+
+		// Jumping to the top address and running off it
+		let code = vec![1105, 1, i128::MAX];
+		let mut machine = Machine::new(code);
+		assert!(matches!(machine.run(), Err(IntcodeError::UnknownOpcode { ip, .. }) if ip == MAX_ADDRESS));
+	}
+}
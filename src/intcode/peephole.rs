@@ -0,0 +1,391 @@
+//! Peephole optimization, for machines with `optimize` set.
+//!
+//! Instructions are decoded once into `Op`s with their operands resolved.
+//! Arithmetic on immediates is folded into a constant, and a few idioms
+//! become superinstructions of their own: `add #0, a, b` and `mul #1, a, b`
+//! are moves, a jump on an immediate condition either always or never
+//! jumps. Writing to memory an `Op` was decoded from drops it, so code that
+//! modifies itself is decoded again the next time it runs.
+
+use super::{ExitCode, Instruction, IntcodeError, Machine, Memory, Operation, ParameterMode, Word, DENSE_LIMIT};
+
+////////////////////////////////////////
+// OPS
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone)]
+enum Operand<W> {
+	Immediate(W),
+	Position(u128),
+	/// Offset from the relative base.
+	Relative(W),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Op<W> {
+	Add(Operand<W>, Operand<W>, Operand<W>),
+	Mul(Operand<W>, Operand<W>, Operand<W>),
+	LessThan(Operand<W>, Operand<W>, Operand<W>),
+	Equals(Operand<W>, Operand<W>, Operand<W>),
+	Input(Operand<W>),
+	Output(Operand<W>),
+	JumpIfTrue(Operand<W>, Operand<W>),
+	JumpIfFalse(Operand<W>, Operand<W>),
+	RelativeBase(Operand<W>),
+	Halt,
+	Move(Operand<W>, Operand<W>),
+	Jump(u128),
+	/// A jump that is never taken.
+	Skip,
+	/// Anything that is bound to fault is left to the interpreter.
+	Interpret,
+}
+
+/// `Op`s by address, next to how many words they were decoded from.
+#[derive(Clone, Default)]
+pub(super) struct Peephole<W> {
+	ops: Vec<Option<(Op<W>, u128)>>,
+	/// `Memory::version` when `ops` last matched it.
+	version: (u64, u64),
+}
+
+impl<W> Peephole<W> {
+	/// Drops every `Op` decoded from `address`.
+	fn invalidate(&mut self, address: u128) {
+		let len = self.ops.len() as u128;
+		for start in address.saturating_sub(3)..(address + 1).min(len) {
+			if let Some((_, words)) = &self.ops[start as usize] {
+				if start + words > address {
+					self.ops[start as usize] = None;
+				}
+			}
+		}
+	}
+}
+
+////////////////////////////////////////
+// OPTIMIZATION
+////////////////////////////////////////
+
+fn fold<W: Word>(operation: Operation, a: &W, b: &W) -> Option<W> {
+	match operation {
+		Operation::Add => a.checked_plus(b),
+		Operation::Mul => a.checked_times(b),
+		Operation::LessThan => Some(W::from_i64((a < b) as i64)),
+		_ => Some(W::from_i64((a == b) as i64)),
+	}
+}
+
+fn arithmetic<W: Word>(operation: Operation, a: Operand<W>, b: Operand<W>, c: Operand<W>) -> Op<W> {
+	let one = W::from_i64(1);
+	match (operation, &a, &b) {
+		(_, Operand::Immediate(x), Operand::Immediate(y)) => {
+			if let Some(value) = fold(operation, x, y) {
+				return Op::Move(Operand::Immediate(value), c);
+			}
+		},
+		(Operation::Add, Operand::Immediate(x), _) if x.is_zero() => return Op::Move(b, c),
+		(Operation::Add, _, Operand::Immediate(y)) if y.is_zero() => return Op::Move(a, c),
+		(Operation::Mul, Operand::Immediate(x), _) if *x == one => return Op::Move(b, c),
+		(Operation::Mul, _, Operand::Immediate(y)) if *y == one => return Op::Move(a, c),
+		_ => {},
+	}
+	match operation {
+		Operation::Add => Op::Add(a, b, c),
+		Operation::Mul => Op::Mul(a, b, c),
+		Operation::LessThan => Op::LessThan(a, b, c),
+		_ => Op::Equals(a, b, c),
+	}
+}
+
+fn jump<W: Word>(if_true: bool, condition: Operand<W>, target: Operand<W>) -> Op<W> {
+	if let Operand::Immediate(value) = &condition {
+		if value.is_zero() == if_true {
+			return Op::Skip;
+		}
+		if let Some(target) = match &target {
+			Operand::Immediate(target) => target.to_address(),
+			_ => None,
+		} {
+			return Op::Jump(target);
+		}
+	}
+	if if_true {
+		Op::JumpIfTrue(condition, target)
+	}
+	else {
+		Op::JumpIfFalse(condition, target)
+	}
+}
+
+/// The `Op` for the instruction at `ip`, and how many words it spans.
+fn decode<W: Word>(memory: &Memory<W>, ip: u128) -> (Op<W>, u128) {
+	let ins = match Instruction::parse(ip, memory) {
+		Ok(ins) => ins,
+		Err(_) => return (Op::Interpret, 1),
+	};
+	let len = ins.opcode.n_parameters() as u128 + 1;
+	let mut operands = Vec::with_capacity(3);
+	for (n, mode) in ins.modes().iter().enumerate() {
+		let word = memory.cell(ip + n as u128 + 1);
+		operands.push(match mode {
+			ParameterMode::Immediate => Operand::Immediate(word),
+			ParameterMode::Relative => Operand::Relative(word),
+			ParameterMode::Position => match word.to_address() {
+				Some(address) => Operand::Position(address),
+				None => return (Op::Interpret, len),
+			},
+		});
+	}
+	if let Some(n) = ins.opcode.output_parameter() {
+		if let Operand::Immediate(_) = operands[n] {
+			return (Op::Interpret, len);
+		}
+	}
+
+	let mut operands = operands.into_iter();
+	let mut next = || operands.next().unwrap();
+	let op = match ins.opcode {
+		Operation::Add | Operation::Mul | Operation::LessThan | Operation::Equals => {
+			arithmetic(ins.opcode, next(), next(), next())
+		},
+		Operation::Input => Op::Input(next()),
+		Operation::Output => Op::Output(next()),
+		Operation::JumpIfTrue => jump(true, next(), next()),
+		Operation::JumpIfFalse => jump(false, next(), next()),
+		Operation::RelativeBase => Op::RelativeBase(next()),
+		Operation::Halt => Op::Halt,
+	};
+	(op, len)
+}
+
+////////////////////////////////////////
+// EXECUTION
+////////////////////////////////////////
+
+impl<W: Word> Machine<W> {
+	/// `run` for machines with `optimize` set. Kept apart from the plain
+	/// interpreter loop, which gets slower when this is inlined into it.
+	#[inline(never)]
+	pub(super) fn run_optimized(&mut self) -> Result<ExitCode, IntcodeError<W>> {
+		if self.peephole.version != self.memory.version() {
+			self.peephole.ops.clear();
+		}
+		let exit = self.dispatch();
+		self.peephole.version = self.memory.version();
+		exit
+	}
+
+	fn op_at(&mut self, ip: u128) -> (Op<W>, u128) {
+		if ip >= DENSE_LIMIT {
+			return decode(&self.memory, ip);
+		}
+		let slot = ip as usize;
+		if slot >= self.peephole.ops.len() {
+			self.peephole.ops.resize(slot + 1, None);
+		}
+		if let Some(entry) = &self.peephole.ops[slot] {
+			return entry.clone();
+		}
+		let entry = decode(&self.memory, ip);
+		self.peephole.ops[slot] = Some(entry.clone());
+		entry
+	}
+
+	fn negative(&self, address: W) -> IntcodeError<W> {
+		IntcodeError::NegativeAddress {
+			ip: self.ip,
+			address,
+			excerpt: self.memory.excerpt(self.ip),
+		}
+	}
+
+	fn relative(&self, offset: &W) -> Result<u128, IntcodeError<W>> {
+		let address = offset.plus(&self.memory.base);
+		address.to_address().ok_or_else(|| self.negative(address))
+	}
+
+	fn operand(&self, operand: &Operand<W>) -> Result<W, IntcodeError<W>> {
+		match operand {
+			Operand::Immediate(value) => Ok(value.clone()),
+			Operand::Position(address) => Ok(self.memory.cell(*address)),
+			Operand::Relative(offset) => Ok(self.memory.cell(self.relative(offset)?)),
+		}
+	}
+
+	fn set_operand(&mut self, operand: &Operand<W>, value: W) -> Result<(), IntcodeError<W>> {
+		let address = match operand {
+			Operand::Position(address) => *address,
+			Operand::Relative(offset) => self.relative(offset)?,
+			Operand::Immediate(_) => unreachable!(),
+		};
+		self.memory.set(address, value);
+		self.peephole.invalidate(address);
+		Ok(())
+	}
+
+	fn dispatch(&mut self) -> Result<ExitCode, IntcodeError<W>> {
+		loop {
+			let (op, len) = self.op_at(self.ip);
+			let mut next = self.ip + len;
+			match &op {
+				Op::Add(a, b, c) | Op::Mul(a, b, c) | Op::LessThan(a, b, c) | Op::Equals(a, b, c) => {
+					let (x, y) = (self.operand(a)?, self.operand(b)?);
+					let (operation, value) = match op {
+						Op::Add(..) => (Operation::Add, self.arithmetic.add(&x, &y)),
+						Op::Mul(..) => (Operation::Mul, self.arithmetic.mul(&x, &y)),
+						Op::LessThan(..) => (Operation::LessThan, Some(W::from_i64((x < y) as i64))),
+						_ => (Operation::Equals, Some(W::from_i64((x == y) as i64))),
+					};
+					let value = value.ok_or_else(|| IntcodeError::Overflow {
+						ip: self.ip,
+						operation,
+						operands: [x, y],
+						excerpt: self.memory.excerpt(self.ip),
+					})?;
+					self.set_operand(c, value)?;
+				},
+				Op::Input(a) => {
+					let value = match self.input.pop_front() {
+						Some(value) => value,
+						None => return Ok(ExitCode::Wait),
+					};
+					self.set_operand(a, value)?;
+				},
+				Op::Output(a) => {
+					let value = self.operand(a)?;
+					self.output.push(value);
+					self.executed += 1;
+					self.ip = next;
+					return Ok(ExitCode::Print);
+				},
+				Op::JumpIfTrue(a, b) | Op::JumpIfFalse(a, b) => {
+					if self.operand(a)?.is_zero() != matches!(op, Op::JumpIfTrue(..)) {
+						let target = self.operand(b)?;
+						next = target.to_address().ok_or_else(|| self.negative(target))?;
+					}
+				},
+				Op::RelativeBase(a) => {
+					let increment = self.operand(a)?;
+					self.memory.add_to_base(increment);
+				},
+				Op::Halt => return Ok(ExitCode::Halt),
+				Op::Move(a, b) => {
+					let value = self.operand(a)?;
+					self.set_operand(b, value)?;
+				},
+				Op::Jump(target) => next = *target,
+				Op::Skip => {},
+				Op::Interpret => match self.step()? {
+					Some(exit) => return Ok(exit),
+					None => continue,
+				},
+			}
+			self.executed += 1;
+			self.ip = next;
+		}
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::{parse_program, Arithmetic};
+	use super::*;
+	use std::fs::read_to_string;
+
+	fn op(code: &[i128]) -> Op<i128> {
+		decode(&Memory::new(code.to_vec()), 0).0
+	}
+
+	/// Runs `code` both ways, checking they agree on everything.
+	fn compare(code: Vec<i128>, input: &[i128], arithmetic: Arithmetic) -> Machine {
+		let mut machines = [Machine::new(code.clone()), Machine::new(code)];
+		machines[1].optimize = true;
+		let mut results = Vec::new();
+		for machine in machines.iter_mut() {
+			machine.arithmetic = arithmetic;
+			machine.input.extend(input);
+			results.push(machine.run_until_halt());
+		}
+		let [plain, optimized] = machines;
+		assert_eq!(results[0], results[1]);
+		assert_eq!(plain.output, optimized.output);
+		assert_eq!(plain.memory.to_vec(), optimized.memory.to_vec());
+		assert_eq!((plain.ip, plain.executed), (optimized.ip, optimized.executed));
+		optimized
+	}
+
+	#[test]
+	fn peephole_test1() {
+		use Operand::*;
+		assert_eq!(Op::Move(Immediate(5), Position(9)), op(&[1101, 2, 3, 9]));
+		assert_eq!(Op::Move(Immediate(1), Relative(2)), op(&[21107, 2, 3, 2]));
+		assert_eq!(Op::Move(Relative(-1), Position(9)), op(&[1201, -1, 0, 9]));
+		assert_eq!(Op::Move(Position(7), Position(9)), op(&[102, 1, 7, 9]));
+		assert_eq!(Op::Jump(12), op(&[1105, 1, 12]));
+		assert_eq!(Op::Skip, op(&[1105, 0, 12]));
+		assert_eq!(Op::Jump(12), op(&[1106, 0, 12]));
+		assert_eq!(Op::JumpIfFalse(Immediate(0), Position(12)), op(&[106, 0, 12]));
+		assert_eq!(Op::Add(Position(1), Immediate(7), Position(9)), op(&[1001, 1, 7, 9]));
+		// Overflows are left for run time
+		assert_eq!(
+			Op::Mul(Immediate(i128::MAX), Immediate(2), Position(9)),
+			op(&[1102, i128::MAX, 2, 9])
+		);
+		assert_eq!(Op::Interpret, op(&[1, -1, 0, 9]));
+		assert_eq!(Op::Interpret, op(&[11101, 1, 1, 0]));
+		assert_eq!(Op::Interpret, op(&[42]));
+	}
+
+	#[test]
+	fn peephole_test2() {
+		let day5 = parse_program(&read_to_string("input/day5/input1.txt").unwrap()).unwrap();
+		let day9 = parse_program(&read_to_string("input/day9/input1.txt").unwrap()).unwrap();
+		for input in [1, 5] {
+			compare(day5.clone(), &[input], Arithmetic::Checked);
+		}
+		for input in [1, 2] {
+			compare(day9.clone(), &[input], Arithmetic::Checked);
+		}
+		compare(vec![1102, i128::MAX, 2, 9, 99], &[], Arithmetic::Checked);
+		compare(vec![1102, i128::MAX, 2, 9, 99], &[], Arithmetic::Saturating);
+		compare(vec![109, -5, 1201, 0, 1, 0, 99], &[], Arithmetic::Checked);
+		compare(vec![3, 0, 4, 0, 3, 0, 99], &[7], Arithmetic::Checked);
+		compare(vec![1105, 1, -1], &[], Arithmetic::Checked);
+	}
+
+	#[test]
+	fn peephole_test3() {
+		#[rustfmt::skip]
+		let mut code = vec![
+			4, 40,               // out [40]
+			1001, 40, 1, 40,     // add [40], #1, [40]
+			1002, 4, 2, 4,       // mul [4], #2, [4]          ; doubles the #1 above
+			1007, 40, 5, 41,     // lt [40], #5, [41]
+			1002, 41, -1, 42,    // mul [41], #-1, [42]
+			1001, 42, 1106, 22,  // add [42], #1106, [22]     ; jt below becomes jf
+			1105, 1, 0,          // jt #1, #0
+			1101, 0, 99, 25,     // add #0, #99, [25]         ; overwrites itself
+			1105, 1, 25,         // jt #1, #25
+		];
+		code.resize(43, 0);
+		let machine = compare(code, &[], Arithmetic::Checked);
+		assert_eq!(vec![0, 1, 3], machine.output);
+		assert_eq!(25, machine.ip);
+
+		// Memory changed from outside between runs
+		let mut machine = Machine::new(vec![104, 1, 1105, 1, 0]);
+		machine.optimize = true;
+		assert_eq!(Ok(ExitCode::Print), machine.run());
+		machine.memory.set(1, 2);
+		assert_eq!(Ok(ExitCode::Print), machine.run());
+		machine.memory.set(2, 99);
+		assert_eq!(Ok(ExitCode::Halt), machine.run());
+		assert_eq!(vec![1, 2], machine.output);
+	}
+}
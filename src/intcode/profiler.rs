@@ -0,0 +1,132 @@
+//! Counts of what a program spends its time on, gathered through `Tracer`.
+
+use super::trace::{Record, Tracer};
+use super::{ExitCode, IntcodeError, Machine, Operation};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+////////////////////////////////////////
+// PROFILE
+////////////////////////////////////////
+
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+	pub total: u64,
+	pub by_operation: BTreeMap<Operation, u64>,
+	pub by_address: HashMap<u128, u64>,
+	/// Backward jump targets and how many times the jump was taken.
+	pub loops: HashMap<u128, u64>,
+}
+
+fn sorted(counts: &HashMap<u128, u64>) -> Vec<(u128, u64)> {
+	let mut counts: Vec<(u128, u64)> = counts.iter().map(|(a, n)| (*a, *n)).collect();
+	counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+	counts
+}
+
+impl Tracer for Profile {
+	fn record(&mut self, record: &Record) {
+		let operation = Operation::try_from(record.instruction % 100).unwrap();
+		self.total += 1;
+		*self.by_operation.entry(operation).or_insert(0) += 1;
+		*self.by_address.entry(record.ip).or_insert(0) += 1;
+
+		let taken = match operation {
+			Operation::JumpIfTrue => record.operands[0] != 0,
+			Operation::JumpIfFalse => record.operands[0] == 0,
+			_ => false,
+		};
+		if taken && record.operands[1] <= record.ip as i128 {
+			*self.loops.entry(record.operands[1] as u128).or_insert(0) += 1;
+		}
+	}
+}
+
+impl Profile {
+	fn percent(&self, n: u64) -> f64 {
+		100.0 * n as f64 / self.total.max(1) as f64
+	}
+
+	/// Human readable report, listing the `top` hottest addresses and loops.
+	pub fn table(&self, top: usize) -> String {
+		let mut out = String::new();
+		writeln!(out, "total instructions: {}", self.total).unwrap();
+
+		writeln!(out, "\n{:<10} {:>12} {:>8}", "operation", "count", "%").unwrap();
+		let mut operations: Vec<_> = self.by_operation.iter().collect();
+		operations.sort_by(|a, b| b.1.cmp(a.1));
+		for (operation, n) in operations {
+			writeln!(out, "{:<10} {:>12} {:>7.2}%", operation.mnemonic(), n, self.percent(*n)).unwrap();
+		}
+
+		writeln!(out, "\n{:<10} {:>12} {:>8}", "address", "count", "%").unwrap();
+		for (address, n) in sorted(&self.by_address).into_iter().take(top) {
+			writeln!(out, "{:<10} {:>12} {:>7.2}%", address, n, self.percent(n)).unwrap();
+		}
+
+		writeln!(out, "\n{:<10} {:>12}", "loop head", "iterations").unwrap();
+		for (address, n) in sorted(&self.loops).into_iter().take(top) {
+			writeln!(out, "{:<10} {:>12}", address, n).unwrap();
+		}
+		out
+	}
+
+	/// Every counter as `kind,key,count` rows.
+	pub fn csv(&self) -> String {
+		let mut out = String::from("kind,key,count\n");
+		writeln!(out, "total,,{}", self.total).unwrap();
+		for (operation, n) in &self.by_operation {
+			writeln!(out, "operation,{},{}", operation.mnemonic(), n).unwrap();
+		}
+		for (address, n) in sorted(&self.by_address) {
+			writeln!(out, "address,{},{}", address, n).unwrap();
+		}
+		for (address, n) in sorted(&self.loops) {
+			writeln!(out, "loop,{},{}", address, n).unwrap();
+		}
+		out
+	}
+}
+
+/// Runs `machine` until it halts or waits for input, profiling everything
+/// it executes on the way.
+pub fn profile(machine: &mut Machine) -> Result<Profile, IntcodeError> {
+	let mut profile = Profile::default();
+	while machine.run_traced(&mut profile)? == ExitCode::Print {}
+	Ok(profile)
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn profiler_test1() {
+		let code = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+		let profile = profile(&mut Machine::new(code)).unwrap();
+		assert_eq!(16 * 5, profile.total);
+		assert_eq!(Some(&16), profile.by_operation.get(&Operation::Output));
+		assert_eq!(Some(&16), profile.by_address.get(&12));
+		assert_eq!(vec![(0, 15)], sorted(&profile.loops));
+	}
+
+	#[test]
+	fn profiler_test2() {
+		let mut machine = Machine::new(vec![3, 10, 1001, 10, -1, 10, 1005, 10, 2, 99]);
+		machine.input.push_back(3);
+		let profile = profile(&mut machine).unwrap();
+		assert_eq!(7, profile.total);
+		assert_eq!(vec![(2, 2)], sorted(&profile.loops));
+
+		let csv = profile.csv();
+		assert!(csv.starts_with("kind,key,count\ntotal,,7\noperation,add,3\n"));
+		assert!(csv.ends_with("loop,2,2\n"));
+		assert!(profile.table(5).contains("add                   3   42.86%"));
+	}
+}
@@ -0,0 +1,314 @@
+//! Search for the phase settings that get the most (or least) out of a
+//! network of machines, e.g. the amplifiers of day 7.
+
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicUsize};
+use std::thread;
+
+////////////////////////////////////////
+// PROBLEM
+////////////////////////////////////////
+
+/// Every sequence of `length` phases taken from `alphabet`, with or without
+/// using the same phase twice.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Space {
+	pub alphabet: Vec<i128>,
+	pub length: usize,
+	pub repeats: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Objective {
+	Max,
+	Min,
+}
+
+impl Objective {
+	fn better(self, a: i128, b: i128) -> bool {
+		match self {
+			Objective::Max => a > b,
+			Objective::Min => a < b,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Strategy {
+	/// Every sequence, one after the other.
+	Exhaustive,
+	/// Depth first, skipping the prefixes whose `Evaluate::bound` cannot
+	/// beat what has been found already. Only worth it with an `Evaluate`
+	/// that implements `bound`: closures, day 7's amplifiers among them,
+	/// have none, and for them this is the same full search as `Exhaustive`.
+	Pruned,
+	/// Every sequence, spread over as many threads as the machine can run
+	/// at once, each taking the next first phase nobody has taken yet.
+	Threaded,
+}
+
+pub trait Evaluate: Sync {
+	/// Signal produced by a full phase sequence.
+	fn evaluate(&self, phases: &[i128]) -> i128;
+
+	/// Best signal any sequence starting with `prefix` could reach, if it
+	/// can be told without running them. Only `Strategy::Pruned` uses it,
+	/// and it prunes nothing while this is `None`.
+	fn bound(&self, _prefix: &[i128], _objective: Objective) -> Option<i128> {
+		None
+	}
+}
+
+impl<F: Fn(&[i128]) -> i128 + Sync> Evaluate for F {
+	fn evaluate(&self, phases: &[i128]) -> i128 {
+		self(phases)
+	}
+}
+
+////////////////////////////////////////
+// SEARCH
+////////////////////////////////////////
+
+/// The `k` best sequences found so far, best first. Equal signals are
+/// ordered by their phases.
+struct Best {
+	objective: Objective,
+	k: usize,
+	found: Vec<(i128, Vec<i128>)>,
+}
+
+impl Best {
+	fn new(objective: Objective, k: usize) -> Self {
+		Self {
+			objective,
+			k,
+			found: Vec::new(),
+		}
+	}
+
+	fn insert(&mut self, signal: i128, phases: &[i128]) {
+		let objective = self.objective;
+		let position = self
+			.found
+			.iter()
+			.position(|(s, p)| objective.better(signal, *s) || (signal == *s && phases < p.as_slice()))
+			.unwrap_or(self.found.len());
+		if position < self.k {
+			self.found.insert(position, (signal, phases.to_vec()));
+			self.found.truncate(self.k);
+		}
+	}
+
+	/// Whether a sequence reaching `bound` could still make it in.
+	fn admits(&self, bound: i128) -> bool {
+		self.found.len() < self.k || !self.objective.better(self.found[self.k - 1].0, bound)
+	}
+}
+
+struct Search<'a, E: Evaluate> {
+	space: &'a Space,
+	evaluate: &'a E,
+	prune: bool,
+}
+
+impl<'a, E: Evaluate> Search<'a, E> {
+	fn walk(&self, prefix: &mut Vec<i128>, used: &mut Vec<bool>, best: &mut Best) {
+		if prefix.len() == self.space.length {
+			best.insert(self.evaluate.evaluate(prefix), prefix);
+			return;
+		}
+		if self.prune && !prefix.is_empty() {
+			if let Some(bound) = self.evaluate.bound(prefix, best.objective) {
+				if !best.admits(bound) {
+					return;
+				}
+			}
+		}
+		let mut next: Vec<usize> = (0..self.space.alphabet.len())
+			.filter(|n| self.space.repeats || !used[*n])
+			.collect();
+		if self.prune {
+			// Most promising first, so that the bounds cut more
+			let objective = best.objective;
+			let mut bounds: Vec<(usize, Option<i128>)> = next
+				.iter()
+				.map(|n| {
+					prefix.push(self.space.alphabet[*n]);
+					let bound = self.evaluate.bound(prefix, objective);
+					prefix.pop();
+					(*n, bound)
+				})
+				.collect();
+			bounds.sort_by(|a, b| match (a.1, b.1) {
+				(Some(a), Some(b)) if objective.better(a, b) => Ordering::Less,
+				(Some(a), Some(b)) if objective.better(b, a) => Ordering::Greater,
+				_ => Ordering::Equal,
+			});
+			next = bounds.into_iter().map(|(n, _)| n).collect();
+		}
+		for n in next {
+			used[n] = true;
+			prefix.push(self.space.alphabet[n]);
+			self.walk(prefix, used, best);
+			prefix.pop();
+			used[n] = false;
+		}
+	}
+
+	fn walk_from(&self, first: usize, best: &mut Best) {
+		let mut used = vec![false; self.space.alphabet.len()];
+		used[first] = true;
+		self.walk(&mut vec![self.space.alphabet[first]], &mut used, best);
+	}
+}
+
+/// The `k` best phase sequences of `space` and their signals, best first.
+pub fn search<E: Evaluate>(
+	space: &Space,
+	objective: Objective,
+	strategy: Strategy,
+	k: usize,
+	evaluate: &E,
+) -> Vec<(i128, Vec<i128>)> {
+	let search = Search {
+		space,
+		evaluate,
+		prune: strategy == Strategy::Pruned,
+	};
+	let mut best = Best::new(objective, k);
+	if k == 0 {
+		return best.found;
+	}
+	if strategy != Strategy::Threaded || space.length == 0 {
+		search.walk(&mut Vec::new(), &mut vec![false; space.alphabet.len()], &mut best);
+		return best.found;
+	}
+
+	let next = AtomicUsize::new(0);
+	let workers = thread::available_parallelism().map_or(1, |n| n.get());
+	let found: Vec<Vec<(i128, Vec<i128>)>> = thread::scope(|scope| {
+		let threads: Vec<_> = (0..workers.min(space.alphabet.len()))
+			.map(|_| {
+				let (search, next) = (&search, &next);
+				scope.spawn(move || {
+					let mut best = Best::new(objective, k);
+					loop {
+						let first = next.fetch_add(1, atomic::Ordering::Relaxed);
+						if first >= search.space.alphabet.len() {
+							return best.found;
+						}
+						search.walk_from(first, &mut best);
+					}
+				})
+			})
+			.collect();
+		threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+	});
+	for (signal, phases) in found.into_iter().flatten() {
+		best.insert(signal, &phases);
+	}
+	best.found
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	/// Reads the phases as the digits of a number, counting the sequences
+	/// it is asked to evaluate.
+	struct Digits(AtomicUsize);
+
+	fn number(phases: &[i128]) -> i128 {
+		phases.iter().fold(0, |n, digit| n * 10 + digit)
+	}
+
+	impl Evaluate for Digits {
+		fn evaluate(&self, phases: &[i128]) -> i128 {
+			self.0.fetch_add(1, atomic::Ordering::Relaxed);
+			number(phases)
+		}
+
+		fn bound(&self, prefix: &[i128], objective: Objective) -> Option<i128> {
+			let rest = 4 - prefix.len() as u32;
+			let n = number(prefix) * 10i128.pow(rest);
+			match objective {
+				Objective::Max => Some(n + 10i128.pow(rest) - 1),
+				Objective::Min => Some(n),
+			}
+		}
+	}
+
+	fn space(repeats: bool) -> Space {
+		Space {
+			alphabet: vec![1, 2, 3, 4],
+			length: 4,
+			repeats,
+		}
+	}
+
+	#[test]
+	fn search_test1() {
+		let evaluate = |phases: &[i128]| number(phases);
+		assert_eq!(
+			vec![(4321, vec![4, 3, 2, 1]), (4312, vec![4, 3, 1, 2])],
+			search(&space(false), Objective::Max, Strategy::Exhaustive, 2, &evaluate)
+		);
+		assert_eq!(
+			vec![(1111, vec![1, 1, 1, 1])],
+			search(&space(true), Objective::Min, Strategy::Exhaustive, 1, &evaluate)
+		);
+		assert_eq!(
+			24,
+			search(&space(false), Objective::Min, Strategy::Exhaustive, 100, &evaluate).len()
+		);
+		assert!(search(&space(false), Objective::Max, Strategy::Exhaustive, 0, &evaluate).is_empty());
+	}
+
+	#[test]
+	fn search_test2() {
+		for objective in [Objective::Max, Objective::Min] {
+			for repeats in [false, true] {
+				let exhaustive = Digits(AtomicUsize::new(0));
+				let pruned = Digits(AtomicUsize::new(0));
+				let threaded = Digits(AtomicUsize::new(0));
+				let expected = search(&space(repeats), objective, Strategy::Exhaustive, 3, &exhaustive);
+				assert_eq!(
+					expected,
+					search(&space(repeats), objective, Strategy::Pruned, 3, &pruned)
+				);
+				assert_eq!(
+					expected,
+					search(&space(repeats), objective, Strategy::Threaded, 3, &threaded)
+				);
+				assert_eq!(exhaustive.0.into_inner(), threaded.0.into_inner());
+				assert!(pruned.0.into_inner() < 10);
+			}
+		}
+	}
+
+	#[test]
+	fn search_test3() {
+		// Equal signals keep the lowest phases first
+		let evaluate = |phases: &[i128]| phases.iter().sum();
+		assert_eq!(
+			vec![(10, vec![1, 2, 3, 4]), (10, vec![1, 2, 4, 3])],
+			search(&space(false), Objective::Max, Strategy::Threaded, 2, &evaluate)
+		);
+
+		// More first phases than threads
+		let wide = Space {
+			alphabet: (0..200).collect(),
+			length: 2,
+			repeats: false,
+		};
+		assert_eq!(
+			vec![(397, vec![198, 199])],
+			search(&wide, Objective::Max, Strategy::Threaded, 1, &evaluate)
+		);
+	}
+}
@@ -0,0 +1,383 @@
+//! Checkpoints of a machine, to resume it later or to try several things
+//! from the same point. Snapshots are plain text:
+//!
+//! ```text
+//! intcode snapshot
+//! ip 4
+//! rb 0
+//! input 1,2
+//! output 7
+//! executed 2
+//! arithmetic checked
+//! instruction-limit 1000
+//! len 8
+//! memory 3,7,4,7,1105,1,0,7
+//! used 0-7
+//! sparse 1048576=5,1048577=0
+//! ```
+//!
+//! `memory` holds the cells from address 0 on, `used` the ranges of them
+//! counted in `Memory::cells`, and `sparse` the cells written far beyond
+//! them, zeros included. `instruction-limit` and `cell-limit` are only
+//! there when the machine has them; a deadline cannot be saved.
+
+use super::{Arithmetic, Machine, Memory, MAX_ADDRESS};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const HEADER: &str = "intcode snapshot";
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseSnapshotError(pub String);
+
+impl fmt::Display for ParseSnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid snapshot: {}", self.0)
+	}
+}
+
+impl Error for ParseSnapshotError {}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+	values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn split(values: &str) -> impl Iterator<Item = &str> {
+	values.split(',').filter(|v| !v.is_empty())
+}
+
+fn count<T: FromStr>(text: &str) -> Result<T, Box<dyn Error>> {
+	text.parse()
+		.map_err(|_| Box::new(ParseSnapshotError(format!("`{}` is not a count", text))).into())
+}
+
+fn arithmetic_name(arithmetic: Arithmetic) -> &'static str {
+	match arithmetic {
+		Arithmetic::Wrapping => "wrapping",
+		Arithmetic::Checked => "checked",
+		Arithmetic::Saturating => "saturating",
+	}
+}
+
+impl Machine {
+	/// An independent copy of the machine, which can be run without
+	/// disturbing this one.
+	pub fn fork(&self) -> Machine {
+		self.clone()
+	}
+
+	/// Fails without writing anything if the machine has a deadline.
+	pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		if self.limits.deadline.is_some() {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"machines with a deadline cannot be saved",
+			));
+		}
+		let memory = &self.memory;
+		let mut sparse: Vec<(u128, i128)> = memory
+			.pages
+			.iter()
+			.flat_map(|(page, cells)| {
+				cells
+					.cells
+					.iter()
+					.enumerate()
+					.map(move |(n, value)| (page * super::PAGE_SIZE + n as u128, *value))
+			})
+			.filter(|(address, _)| memory.is_used(*address))
+			.collect();
+		sparse.sort_unstable();
+		let mut used: Vec<(u128, u128)> = Vec::new();
+		for address in (0..memory.dense.len() as u128).filter(|a| memory.is_used(*a)) {
+			match used.last_mut() {
+				Some((_, last)) if *last + 1 == address => *last = address,
+				_ => used.push((address, address)),
+			}
+		}
+
+		writeln!(writer, "{}", HEADER)?;
+		writeln!(writer, "ip {}", self.ip)?;
+		writeln!(writer, "rb {}", memory.base)?;
+		writeln!(writer, "input {}", join(self.input.iter()))?;
+		writeln!(writer, "output {}", join(self.output.iter()))?;
+		writeln!(writer, "executed {}", self.executed)?;
+		writeln!(writer, "arithmetic {}", arithmetic_name(self.arithmetic))?;
+		if let Some(n) = self.limits.instructions {
+			writeln!(writer, "instruction-limit {}", n)?;
+		}
+		if let Some(n) = self.limits.cells {
+			writeln!(writer, "cell-limit {}", n)?;
+		}
+		writeln!(writer, "len {}", memory.len)?;
+		writeln!(writer, "memory {}", join(memory.dense.iter()))?;
+		let range = |(first, last): &(u128, u128)| {
+			if first == last {
+				first.to_string()
+			}
+			else {
+				format!("{}-{}", first, last)
+			}
+		};
+		writeln!(writer, "used {}", join(used.iter().map(range)))?;
+		writeln!(
+			writer,
+			"sparse {}",
+			join(sparse.iter().map(|(address, value)| format!("{}={}", address, value)))
+		)?;
+		writer.flush()
+	}
+
+	pub fn load<R: BufRead>(reader: R) -> Result<Machine, Box<dyn Error>> {
+		let invalid = |message: String| Box::new(ParseSnapshotError(message));
+		let mut lines = reader.lines();
+		if lines.next().transpose()?.as_deref() != Some(HEADER) {
+			return Err(invalid(String::from("missing header")));
+		}
+
+		let mut machine = Machine::new(Vec::new());
+		let mut len = None;
+		let mut used: Option<Vec<(u128, u128)>> = None;
+		for line in lines {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			let mut parts = line.splitn(2, ' ');
+			let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or("").trim());
+			let number = |text: &str| {
+				text.parse::<i128>()
+					.map_err(|_| invalid(format!("`{}` is not a number", text)))
+			};
+			let address = |text: &str| {
+				text.parse::<u128>()
+					.ok()
+					.filter(|address| *address <= MAX_ADDRESS)
+					.ok_or_else(|| invalid(format!("`{}` is not an address", text)))
+			};
+			match key {
+				"ip" => machine.ip = address(value)?,
+				"rb" => machine.memory.base = number(value)?,
+				"input" => machine.input = split(value).map(number).collect::<Result<_, _>>()?,
+				"output" => machine.output = split(value).map(number).collect::<Result<_, _>>()?,
+				"executed" => machine.executed = count(value)?,
+				"arithmetic" => {
+					machine.arithmetic = [Arithmetic::Wrapping, Arithmetic::Checked, Arithmetic::Saturating]
+						.iter()
+						.copied()
+						.find(|a| arithmetic_name(*a) == value)
+						.ok_or_else(|| invalid(format!("unknown arithmetic `{}`", value)))?
+				},
+				"instruction-limit" => machine.limits.instructions = Some(count(value)?),
+				"cell-limit" => machine.limits.cells = Some(count(value)?),
+				"len" => {
+					let n: u128 = count(value)?;
+					if n > MAX_ADDRESS + 1 {
+						return Err(invalid(format!("`{}` cells do not fit in memory", value)));
+					}
+					len = Some(n);
+				},
+				"memory" => {
+					let base = machine.memory.base;
+					machine.memory = Memory::new(split(value).map(number).collect::<Result<_, _>>()?);
+					machine.memory.base = base;
+				},
+				"used" => {
+					for range in split(value) {
+						let mut ends = range.splitn(2, '-');
+						let first = address(ends.next().unwrap())?;
+						let last = ends.next().map_or(Ok(first), address)?;
+						if first > last {
+							return Err(invalid(format!("`{}` is not a range", range)));
+						}
+						used.get_or_insert_with(Vec::new).push((first, last));
+					}
+				},
+				"sparse" => {
+					for cell in split(value) {
+						let mut parts = cell.splitn(2, '=');
+						let at = address(parts.next().unwrap())?;
+						let value = number(
+							parts
+								.next()
+								.ok_or_else(|| invalid(format!("`{}` has no value", cell)))?,
+						)?;
+						machine.memory.set(at, value);
+					}
+				},
+				_ => return Err(invalid(format!("unknown field `{}`", key))),
+			}
+		}
+		machine.memory.len = len.ok_or_else(|| invalid(String::from("missing len")))?;
+
+		// Every cell of `memory` starts out used, which is all there is to
+		// go by without a `used` field
+		let dense = machine.memory.dense.len();
+		let mut keep = vec![used.is_none(); dense];
+		for (first, last) in used.unwrap_or_default() {
+			if last >= dense as u128 {
+				return Err(invalid(format!("used cell {} is past `memory`", last)));
+			}
+			keep[first as usize..=last as usize].fill(true);
+		}
+		for (address, keep) in keep.into_iter().enumerate() {
+			if !keep {
+				machine.memory.forget(address as u128);
+			}
+		}
+		Ok(machine)
+	}
+
+	pub fn save_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.save(BufWriter::new(File::create(path)?))
+	}
+
+	pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Machine, Box<dyn Error>> {
+		Machine::load(BufReader::new(File::open(path)?))
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::ExitCode;
+	use super::*;
+
+	/// Echoes its inputs forever.
+	fn echo() -> Vec<i128> {
+		vec![3, 7, 4, 7, 1105, 1, 0, 0]
+	}
+
+	#[test]
+	fn snapshot_test1() {
+		let mut machine = Machine::new(echo());
+		machine.input.extend(vec![7, 1, 2]);
+		assert_eq!(Ok(ExitCode::Print), machine.run());
+		machine.memory.set(1 << 20, 5);
+		machine.memory.add_to_base(-3);
+
+		let mut text = Vec::new();
+		machine.save(&mut text).unwrap();
+		let text = String::from_utf8(text).unwrap();
+		let expected = concat!(
+			"intcode snapshot\nip 4\nrb -3\ninput 1,2\noutput 7\nexecuted 2\narithmetic checked\n",
+			"len 1048577\nmemory 3,7,4,7,1105,1,0,7\nused 0-7\nsparse 1048576=5\n"
+		);
+		assert_eq!(expected, text);
+
+		let mut loaded = Machine::load(text.as_bytes()).unwrap();
+		assert_eq!(machine.memory.to_vec(), loaded.memory.to_vec());
+		assert_eq!(-3, loaded.memory.base);
+		while loaded.run() == Ok(ExitCode::Print) {}
+		assert_eq!(vec![7, 1, 2], loaded.output);
+	}
+
+	#[test]
+	fn snapshot_test2() {
+		let mut machine = Machine::new(echo());
+		assert_eq!(Ok(ExitCode::Wait), machine.run());
+		let mut branches: Vec<Machine> = (1..4).map(|_| machine.fork()).collect();
+		for (n, branch) in branches.iter_mut().enumerate() {
+			branch.input.push_back(n as i128);
+			branch.run().unwrap();
+		}
+		assert_eq!(
+			vec![vec![0], vec![1], vec![2]],
+			branches.iter().map(|b| b.output.clone()).collect::<Vec<_>>()
+		);
+		assert!(machine.output.is_empty());
+	}
+
+	#[test]
+	fn snapshot_test3() {
+		let path = std::env::temp_dir().join(format!("intcode-snapshot-test3-{}", std::process::id()));
+		let mut machine = Machine::new(echo());
+		machine.input.push_back(9);
+		machine.run().unwrap();
+		machine.save_to(&path).unwrap();
+		let loaded = Machine::load_from(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!((machine.ip, machine.output), (loaded.ip, loaded.output));
+
+		assert!(Machine::load("ip 4\n".as_bytes()).is_err());
+		let error = Machine::load("intcode snapshot\nip x\n".as_bytes()).err().unwrap();
+		assert_eq!("invalid snapshot: `x` is not an address", error.to_string());
+		let error = Machine::load("intcode snapshot\nsparse 340282366920938463463374607431768211455=1\n".as_bytes())
+			.err()
+			.unwrap();
+		assert_eq!(
+			"invalid snapshot: `340282366920938463463374607431768211455` is not an address",
+			error.to_string()
+		);
+		assert!(Machine::load("intcode snapshot\nlen 340282366920938463463374607431768211455\n".as_bytes()).is_err());
+	}
+
+	#[test]
+	fn snapshot_test4() {
+		let mut machine = Machine::new(echo());
+		machine.arithmetic = Arithmetic::Saturating;
+		machine.limits.instructions = Some(5);
+		machine.limits.cells = Some(100);
+		machine.input.push_back(1);
+		machine.run().unwrap();
+
+		let mut text = Vec::new();
+		machine.save(&mut text).unwrap();
+		let text = String::from_utf8(text).unwrap();
+		assert!(text.contains("executed 2\narithmetic saturating\ninstruction-limit 5\ncell-limit 100\n"));
+		let mut loaded = Machine::load(text.as_bytes()).unwrap();
+		assert_eq!(2, loaded.executed);
+		assert_eq!(Arithmetic::Saturating, loaded.arithmetic);
+		assert_eq!(machine.limits, loaded.limits);
+		// The budget left carries over
+		loaded.input.extend(vec![2, 3]);
+		assert_eq!(Ok(ExitCode::Print), loaded.run());
+		assert!(loaded.run().is_err());
+		assert_eq!(5, loaded.executed);
+
+		machine.limits.deadline = Some(std::time::Instant::now());
+		let mut text = Vec::new();
+		assert!(machine.save(&mut text).is_err());
+		assert!(text.is_empty());
+	}
+
+	#[test]
+	fn snapshot_test5() {
+		// Writes 0 to [1000] and [1 << 20], then reads into [1001]
+		let code = vec![1101, 0, 0, 1000, 1101, 0, 0, 1 << 20, 3, 1001, 99];
+		let mut machine = Machine::new(code);
+		machine.limits.cells = Some(14);
+		assert_eq!(Ok(ExitCode::Wait), machine.run());
+		assert_eq!(13, machine.memory.cells());
+
+		let mut text = Vec::new();
+		machine.save(&mut text).unwrap();
+		let text = String::from_utf8(text).unwrap();
+		assert!(text.contains("\nused 0-10,1000\nsparse 1048576=0\n"));
+		let mut loaded = Machine::load(text.as_bytes()).unwrap();
+		assert_eq!(13, loaded.memory.cells());
+		assert!(loaded.memory.is_used(1000) && !loaded.memory.is_used(999));
+		assert!(loaded.memory.is_used(1 << 20));
+
+		// Both have exactly the one cell left to read into
+		for machine in [&mut machine, &mut loaded] {
+			machine.input.push_back(4);
+			assert_eq!(Ok(()), machine.run_until_halt());
+			assert_eq!(14, machine.memory.cells());
+		}
+
+		// Without `used`, everything in `memory` counts
+		let old = text.replace("used 0-10,1000\n", "");
+		assert_eq!(1002, Machine::load(old.as_bytes()).unwrap().memory.cells());
+		assert!(Machine::load(text.replace("0-10,1000", "0-1001").as_bytes()).is_err());
+		assert!(Machine::load(text.replace("0-10", "10-0").as_bytes()).is_err());
+	}
+}
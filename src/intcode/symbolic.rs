@@ -0,0 +1,254 @@
+//! Symbolic execution of programs that only add and multiply, where some
+//! memory cells are unknowns. Every cell then holds a polynomial in those
+//! unknowns.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+////////////////////////////////////////
+// POLYNOMIAL
+////////////////////////////////////////
+
+/// Sum of terms, each a coefficient times the unknowns raised to the
+/// exponents of its key. Terms with a zero coefficient are never stored.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Poly {
+	terms: BTreeMap<Vec<u32>, i128>,
+}
+
+impl Poly {
+	pub fn constant(value: i128) -> Self {
+		Poly::default().with_term(Vec::new(), value)
+	}
+
+	/// Unknown `n` out of `count`.
+	pub fn unknown(n: usize, count: usize) -> Self {
+		let mut exponents = vec![0; count];
+		exponents[n] = 1;
+		Poly::default().with_term(exponents, 1)
+	}
+
+	fn with_term(mut self, exponents: Vec<u32>, coefficient: i128) -> Self {
+		let exponents = normalize(exponents);
+		let sum = self.terms.get(&exponents).copied().unwrap_or(0) + coefficient;
+		if sum == 0 {
+			self.terms.remove(&exponents);
+		}
+		else {
+			self.terms.insert(exponents, sum);
+		}
+		self
+	}
+
+	pub fn as_constant(&self) -> Option<i128> {
+		match self.terms.len() {
+			0 => Some(0),
+			1 => self.terms.get(&Vec::new()).copied(),
+			_ => None,
+		}
+	}
+
+	/// `None` on overflow.
+	pub fn checked_add(&self, other: &Poly) -> Option<Poly> {
+		let mut sum = self.clone();
+		for (exponents, coefficient) in &other.terms {
+			let old = sum.terms.get(exponents).copied().unwrap_or(0);
+			sum = sum.with_term(exponents.clone(), coefficient.checked_add(old)? - old);
+		}
+		Some(sum)
+	}
+
+	/// `None` on overflow.
+	pub fn checked_mul(&self, other: &Poly) -> Option<Poly> {
+		let mut product = Poly::default();
+		for (a, x) in &self.terms {
+			for (b, y) in &other.terms {
+				let len = a.len().max(b.len());
+				let exponents = (0..len)
+					.map(|i| a.get(i).unwrap_or(&0) + b.get(i).unwrap_or(&0))
+					.collect();
+				product = product.checked_add(&Poly::default().with_term(exponents, x.checked_mul(*y)?))?;
+			}
+		}
+		Some(product)
+	}
+
+	/// Replaces unknown `n` with `value`. `None` on overflow.
+	pub fn substitute(&self, n: usize, value: i128) -> Option<Poly> {
+		let mut result = Poly::default();
+		for (exponents, coefficient) in &self.terms {
+			let mut exponents = exponents.clone();
+			let power = exponents.get(n).copied().unwrap_or(0);
+			if power > 0 {
+				exponents[n] = 0;
+			}
+			let coefficient = value.checked_pow(power)?.checked_mul(*coefficient)?;
+			result = result.checked_add(&Poly::default().with_term(exponents, coefficient))?;
+		}
+		Some(result)
+	}
+
+	/// Highest power of unknown `n`.
+	pub fn degree(&self, n: usize) -> u32 {
+		self.terms
+			.keys()
+			.map(|e| e.get(n).copied().unwrap_or(0))
+			.max()
+			.unwrap_or(0)
+	}
+
+	/// Coefficient of the term with exactly these exponents.
+	pub fn coefficient(&self, exponents: &[u32]) -> i128 {
+		self.terms.get(&normalize(exponents.to_vec())).copied().unwrap_or(0)
+	}
+}
+
+/// Trailing zero exponents are dropped, so every term has a single key.
+fn normalize(mut exponents: Vec<u32>) -> Vec<u32> {
+	while exponents.last() == Some(&0) {
+		exponents.pop();
+	}
+	exponents
+}
+
+impl fmt::Display for Poly {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.terms.is_empty() {
+			return write!(f, "0");
+		}
+		let terms: Vec<String> = self
+			.terms
+			.iter()
+			.rev()
+			.map(|(exponents, coefficient)| {
+				let mut factors: Vec<String> = exponents
+					.iter()
+					.enumerate()
+					.filter(|(_, e)| **e > 0)
+					.map(|(n, e)| {
+						if *e == 1 {
+							format!("x{}", n)
+						}
+						else {
+							format!("x{}^{}", n, e)
+						}
+					})
+					.collect();
+				if *coefficient != 1 || factors.is_empty() {
+					factors.insert(0, coefficient.to_string());
+				}
+				factors.join("*")
+			})
+			.collect();
+		write!(f, "{}", terms.join(" + "))
+	}
+}
+
+////////////////////////////////////////
+// EXECUTION
+////////////////////////////////////////
+
+/// Programs reaching further out are left to the interpreter, rather than
+/// holding a polynomial for every cell up to there.
+pub const MAX_CELLS: usize = 1 << 16;
+
+/// Runs an add/mul-only program with the cells at `unknowns` holding
+/// unknowns instead of their values, and returns the memory once it halts.
+///
+/// A cell read through an address that depends on the unknowns could hold
+/// anything; it comes out as `None`, and so does anything computed from
+/// it. That is harmless as long as it is overwritten later. The whole run
+/// is `None` when the outcome cannot be told at all: the program writes to
+/// such an address, jumps into an unknown opcode, does anything but add,
+/// multiply and halt, uses an address of `MAX_CELLS` or more, or a
+/// coefficient overflows.
+pub fn run(code: &[i128], unknowns: &[usize]) -> Option<Vec<Option<Poly>>> {
+	let mut memory: Vec<Option<Poly>> = code.iter().map(|value| Some(Poly::constant(*value))).collect();
+	for (n, address) in unknowns.iter().enumerate() {
+		*memory.get_mut(*address)? = Some(Poly::unknown(n, unknowns.len()));
+	}
+
+	let mut ip = 0;
+	loop {
+		let opcode = memory.get(ip)?.as_ref()?.as_constant()?;
+		if opcode == 99 {
+			return Some(memory);
+		}
+		let mut address = |i: usize| -> Option<Option<usize>> {
+			let address = match memory.get(ip + i)?.as_ref().and_then(Poly::as_constant) {
+				Some(address) => usize::try_from(address).ok().filter(|a| *a < MAX_CELLS)?,
+				None => return Some(None),
+			};
+			if address >= memory.len() {
+				memory.resize(address + 1, Some(Poly::default()));
+			}
+			Some(Some(address))
+		};
+		let (a, b, c) = (address(1)?, address(2)?, address(3)??);
+		let operand = |address: Option<usize>| address.and_then(|a| memory[a].as_ref());
+		memory[c] = match (opcode, operand(a), operand(b)) {
+			(1, Some(a), Some(b)) => Some(a.checked_add(b)?),
+			(2, Some(a), Some(b)) => Some(a.checked_mul(b)?),
+			(1, _, _) | (2, _, _) => None,
+			_ => return None,
+		};
+		ip += 4;
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn symbolic_test1() {
+		let x = Poly::unknown(0, 2);
+		let y = Poly::unknown(1, 2);
+		let p = x
+			.checked_mul(&x)
+			.unwrap()
+			.checked_add(&y)
+			.unwrap()
+			.checked_add(&Poly::constant(3))
+			.unwrap();
+		assert_eq!("x0^2 + x1 + 3", p.to_string());
+		assert_eq!((2, 1), (p.degree(0), p.degree(1)));
+		assert_eq!(
+			Some(Poly::constant(13)),
+			p.substitute(0, 2).and_then(|p| p.substitute(1, 6))
+		);
+		assert_eq!(None, p.as_constant());
+		let zero = p.checked_add(&p.checked_mul(&Poly::constant(-1)).unwrap()).unwrap();
+		assert_eq!((Some(0), "0".to_string()), (zero.as_constant(), zero.to_string()));
+		assert_eq!(None, Poly::constant(i128::MAX).checked_add(&Poly::constant(1)));
+	}
+
+	#[test]
+	fn symbolic_test2() {
+		// [0] = [1] * [2] + [1]
+		let code = vec![2, 9, 10, 0, 1, 0, 9, 0, 99, 0, 0];
+		let memory = run(&code, &[9, 10]).unwrap();
+		assert_eq!("x0*x1 + x0", memory[0].as_ref().unwrap().to_string());
+		assert_eq!(Some(Poly::constant(99)), memory[8]);
+
+		// Reading through an unknown address, then overwriting the result
+		let memory = run(&[1, 0, 0, 3, 1, 1, 2, 5, 99], &[1, 2]).unwrap();
+		assert_eq!(None, memory[3]);
+		assert_eq!("x0 + x1", memory[5].as_ref().unwrap().to_string());
+		// Writing through one
+		assert_eq!(None, run(&[1, 0, 0, 0, 99], &[3]));
+		// Not an add/mul program
+		assert_eq!(None, run(&[104, 0, 99], &[]));
+		// Writes past the end grow the memory
+		assert_eq!(13, run(&[1, 0, 0, 12, 99], &[]).unwrap().len());
+		// But not without bound
+		assert_eq!(None, run(&[1, 0, 0, 268_435_456, 99], &[]));
+		assert_eq!(None, run(&[1, 0, 0, MAX_CELLS as i128, 99], &[]));
+	}
+}
@@ -0,0 +1,280 @@
+//! Networks of machines running the same program, wired output to input.
+//!
+//! ```text
+//! ; comments start with a semicolon
+//! node a 4        ; a machine, with the values it reads first
+//! node b 3
+//! node c
+//! input -> a      ; values given to `run`
+//! a -> b, c       ; every output of a goes to both b and c
+//! b -> c          ; c reads from a and b in the order values arrive
+//! c -> output     ; values returned by `run`
+//! ```
+
+use super::{ExitCode, IntcodeError, Machine};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+////////////////////////////////////////
+// ERROR
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TopologyError {
+	/// 1-based line number in the description, 0 for a topology that
+	/// was parsed fine but is used wrongly.
+	pub line: usize,
+	pub text: String,
+	pub message: String,
+}
+
+impl fmt::Display for TopologyError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.line == 0 {
+			return write!(f, "{}", self.message);
+		}
+		write!(f, "line {}: {}\n    {}", self.line, self.message, self.text.trim())
+	}
+}
+
+impl Error for TopologyError {}
+
+////////////////////////////////////////
+// TOPOLOGY
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Endpoint {
+	Input,
+	Output,
+	Node(usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Node {
+	pub name: String,
+	/// Values queued before the machine starts, e.g. a phase setting.
+	pub inputs: Vec<i128>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Topology {
+	pub nodes: Vec<Node>,
+	pub edges: Vec<(Endpoint, Endpoint)>,
+}
+
+impl FromStr for Topology {
+	type Err = TopologyError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let mut topology = Topology::default();
+		for (n, line) in text.lines().enumerate() {
+			let error = |message: String| TopologyError {
+				line: n + 1,
+				text: line.to_string(),
+				message,
+			};
+			let line = line.split(';').next().unwrap().trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			let mut words = line.split_whitespace();
+			if words.next() == Some("node") {
+				let name = match words.next() {
+					Some(name) => name.to_string(),
+					None => return Err(error(String::from("expected a node name"))),
+				};
+				if ["input", "output"].contains(&name.as_str()) || topology.endpoint(&name).is_some() {
+					return Err(error(format!("node `{}` is already defined", name)));
+				}
+				let inputs = words
+					.map(|word| word.parse().map_err(|_| error(format!("invalid number `{}`", word))))
+					.collect::<Result<_, _>>()?;
+				topology.nodes.push(Node { name, inputs });
+				continue;
+			}
+
+			let mut sides = line.splitn(2, "->");
+			let (from, to) = match (sides.next(), sides.next()) {
+				(Some(from), Some(to)) => (from.trim(), to),
+				_ => return Err(error(String::from("expected `node` or `->`"))),
+			};
+			let endpoint = |name: &str| {
+				topology
+					.endpoint(name)
+					.ok_or_else(|| error(format!("unknown node `{}`", name)))
+			};
+			let from = endpoint(from)?;
+			if from == Endpoint::Output {
+				return Err(error(String::from("`output` cannot send values")));
+			}
+			let mut edges = Vec::new();
+			for to in to.split(',') {
+				match endpoint(to.trim())? {
+					Endpoint::Input => return Err(error(String::from("`input` cannot receive values"))),
+					to => edges.push((from, to)),
+				}
+			}
+			topology.edges.extend(edges);
+		}
+		Ok(topology)
+	}
+}
+
+impl Topology {
+	fn endpoint(&self, name: &str) -> Option<Endpoint> {
+		match name {
+			"input" => Some(Endpoint::Input),
+			"output" => Some(Endpoint::Output),
+			_ => self.nodes.iter().position(|node| node.name == name).map(Endpoint::Node),
+		}
+	}
+
+	/// The same network, with node `n` reading `phases[n]` first instead of
+	/// its own inputs. Fails unless there is one phase per node.
+	pub fn with_phases(&self, phases: &[i128]) -> Result<Topology, TopologyError> {
+		if phases.len() != self.nodes.len() {
+			return Err(TopologyError {
+				line: 0,
+				text: String::new(),
+				message: format!("{} phases given for {} nodes", phases.len(), self.nodes.len()),
+			});
+		}
+		let mut topology = self.clone();
+		for (node, phase) in topology.nodes.iter_mut().zip(phases) {
+			node.inputs = vec![*phase];
+		}
+		Ok(topology)
+	}
+
+	/// Runs one machine per node until every one of them has halted or is
+	/// waiting for input nobody is going to send. Nodes take turns in the
+	/// order they were declared, each running until it halts or waits.
+	/// Values sent to a halted node are dropped.
+	pub fn run(&self, code: &[i128], input: &[i128]) -> Result<Vec<i128>, IntcodeError> {
+		let mut machines: Vec<Machine> = self
+			.nodes
+			.iter()
+			.map(|node| {
+				let mut machine = Machine::new(code.to_vec());
+				machine.input = node.inputs.iter().copied().collect();
+				machine
+			})
+			.collect();
+		let mut halted = vec![false; machines.len()];
+		let mut output = Vec::new();
+
+		for value in input {
+			self.send(Endpoint::Input, *value, &mut machines, &mut output);
+		}
+		loop {
+			let mut progress = false;
+			for n in 0..machines.len() {
+				if halted[n] {
+					continue;
+				}
+				let pending = machines[n].input.len();
+				let exit = loop {
+					match machines[n].run()? {
+						ExitCode::Print => {},
+						exit => break exit,
+					}
+				};
+				let values: Vec<i128> = machines[n].output.drain(..).collect();
+				halted[n] = exit == ExitCode::Halt;
+				progress |= halted[n] || !values.is_empty() || machines[n].input.len() != pending;
+				for value in values {
+					self.send(Endpoint::Node(n), value, &mut machines, &mut output);
+				}
+			}
+			if !progress {
+				return Ok(output);
+			}
+		}
+	}
+
+	fn send(&self, from: Endpoint, value: i128, machines: &mut [Machine], output: &mut Vec<i128>) {
+		for (_, to) in self.edges.iter().filter(|(source, _)| *source == from) {
+			match to {
+				Endpoint::Node(n) => machines[*n].input.push_back(value),
+				Endpoint::Output => output.push(value),
+				Endpoint::Input => unreachable!(),
+			}
+		}
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	/// Reads a phase and a signal, outputs `signal * 10 + phase`.
+	fn amplifier() -> Vec<i128> {
+		vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0]
+	}
+
+	#[test]
+	fn topology_test1() {
+		let topology: Topology = "node a 1 ; first\nnode b\ninput -> a\na -> b, output\n\nb -> output"
+			.parse()
+			.unwrap();
+		assert_eq!(2, topology.nodes.len());
+		assert_eq!(vec![1], topology.nodes[0].inputs);
+		assert_eq!(
+			vec![
+				(Endpoint::Input, Endpoint::Node(0)),
+				(Endpoint::Node(0), Endpoint::Node(1)),
+				(Endpoint::Node(0), Endpoint::Output),
+				(Endpoint::Node(1), Endpoint::Output),
+			],
+			topology.edges
+		);
+		assert_eq!(vec![2], topology.with_phases(&[5, 2]).unwrap().nodes[1].inputs);
+		let error = topology.with_phases(&[5]).unwrap_err();
+		assert_eq!("1 phases given for 2 nodes", error.to_string());
+
+		// Any whitespace separates the words of a node
+		let topology: Topology = "node\ta\t7\nnode  b\na -> b".parse().unwrap();
+		assert_eq!(
+			vec!["a", "b"],
+			topology.nodes.iter().map(|node| node.name.as_str()).collect::<Vec<_>>()
+		);
+		assert_eq!(vec![7], topology.nodes[0].inputs);
+	}
+
+	#[test]
+	fn topology_test2() {
+		let error = |text: &str| text.parse::<Topology>().unwrap_err();
+		assert_eq!("unknown node `b`", error("node a\na -> b").message);
+		assert_eq!(2, error("node a\nnode a").line);
+		assert_eq!("`input` cannot receive values", error("node a\na -> input").message);
+		assert_eq!("`output` cannot send values", error("node a\noutput -> a").message);
+		assert_eq!("invalid number `x`", error("node a x").message);
+		assert_eq!("expected `node` or `->`", error("a b").message);
+		assert_eq!("expected a node name", error("node ; nameless").message);
+	}
+
+	#[test]
+	fn topology_test3() {
+		let topology: Topology = "
+			node a 1
+			node b 2
+			node c 3
+			node d
+			input -> a
+			a -> b, c
+			b -> d, output
+			c -> d, output
+			d -> output"
+			.parse()
+			.unwrap();
+		assert_eq!(Ok(vec![12, 13, 142]), topology.run(&amplifier(), &[0]));
+	}
+}
@@ -0,0 +1,404 @@
+//! Recording of every executed instruction, one line each:
+//!
+//! ```text
+//! <ip> <instruction> <operands|-> [w:<address>=<value>] [rb:<base>] [in:<value>] [out:<value>]
+//! ```
+//!
+//! Operands are resolved: the value for parameters that are read and the
+//! address for the one that is written. Halting is not an executed
+//! instruction and is never recorded.
+
+use super::{ExitCode, Instruction, IntcodeError, Machine, Operation};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+////////////////////////////////////////
+// RECORD
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Record {
+	pub ip: u128,
+	pub instruction: i128,
+	pub operands: Vec<i128>,
+	pub write: Option<(u128, i128)>,
+	pub base: Option<i128>,
+	pub input: Option<i128>,
+	pub output: Option<i128>,
+}
+
+impl fmt::Display for Record {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let operands: Vec<String> = self.operands.iter().map(|n| n.to_string()).collect();
+		let operands = if operands.is_empty() {
+			String::from("-")
+		}
+		else {
+			operands.join(",")
+		};
+		write!(f, "{} {} {}", self.ip, self.instruction, operands)?;
+		if let Some((address, value)) = self.write {
+			write!(f, " w:{}={}", address, value)?;
+		}
+		if let Some(base) = self.base {
+			write!(f, " rb:{}", base)?;
+		}
+		if let Some(input) = self.input {
+			write!(f, " in:{}", input)?;
+		}
+		if let Some(output) = self.output {
+			write!(f, " out:{}", output)?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseRecordError(pub String);
+
+impl fmt::Display for ParseRecordError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid trace record `{}`", self.0)
+	}
+}
+
+impl Error for ParseRecordError {}
+
+impl FromStr for Record {
+	type Err = ParseRecordError;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		let invalid = || ParseRecordError(line.to_string());
+		let number = |text: &str| text.parse().map_err(|_| invalid());
+		let mut fields = line.split_whitespace();
+		let mut record = Record {
+			ip: fields
+				.next()
+				.ok_or_else(invalid)
+				.and_then(|f| f.parse().map_err(|_| invalid()))?,
+			instruction: fields.next().ok_or_else(invalid).and_then(number)?,
+			..Record::default()
+		};
+		match fields.next() {
+			Some("-") => {},
+			Some(operands) => record.operands = operands.split(',').map(number).collect::<Result<_, _>>()?,
+			None => return Err(invalid()),
+		}
+		for field in fields {
+			let mut parts = field.splitn(2, ':');
+			let (key, value) = (parts.next().unwrap(), parts.next().ok_or_else(invalid)?);
+			match key {
+				"w" => {
+					let mut parts = value.splitn(2, '=');
+					let address = parts.next().unwrap().parse().map_err(|_| invalid())?;
+					let value = parts.next().ok_or_else(invalid).and_then(number)?;
+					record.write = Some((address, value));
+				},
+				"rb" => record.base = Some(number(value)?),
+				"in" => record.input = Some(number(value)?),
+				"out" => record.output = Some(number(value)?),
+				_ => return Err(invalid()),
+			}
+		}
+		Ok(record)
+	}
+}
+
+////////////////////////////////////////
+// TRACER
+////////////////////////////////////////
+
+/// Anything interested in each instruction the machine executes.
+pub trait Tracer {
+	fn record(&mut self, record: &Record);
+}
+
+impl Tracer for Vec<Record> {
+	fn record(&mut self, record: &Record) {
+		self.push(record.clone());
+	}
+}
+
+/// Writes the records to a trace file as they come. The first I/O error is
+/// kept and reported by `finish`.
+pub struct TraceWriter<W: Write> {
+	writer: W,
+	error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+	pub fn new(writer: W) -> Self {
+		Self { writer, error: None }
+	}
+
+	pub fn finish(mut self) -> io::Result<W> {
+		if let Some(error) = self.error {
+			return Err(error);
+		}
+		self.writer.flush()?;
+		Ok(self.writer)
+	}
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+	fn record(&mut self, record: &Record) {
+		if self.error.is_none() {
+			if let Err(error) = writeln!(self.writer, "{}", record) {
+				self.error = Some(error);
+			}
+		}
+	}
+}
+
+pub fn read_trace<R: BufRead>(reader: R) -> Result<Vec<Record>, Box<dyn Error>> {
+	read_lines(reader)
+}
+
+/// Parses every line that is not blank, e.g. into trace records or
+/// transcript events.
+pub(crate) fn read_lines<T, R>(reader: R) -> Result<Vec<T>, Box<dyn Error>>
+where
+	T: FromStr,
+	T::Err: Error + 'static,
+	R: BufRead,
+{
+	let mut items = Vec::new();
+	for line in reader.lines() {
+		let line = line?;
+		if !line.trim().is_empty() {
+			items.push(line.parse()?);
+		}
+	}
+	Ok(items)
+}
+
+////////////////////////////////////////
+// TRACED EXECUTION
+////////////////////////////////////////
+
+impl Machine {
+	/// Like `step`, also handing the executed instruction to `tracer`.
+	pub fn step_traced(&mut self, tracer: &mut dyn Tracer) -> Result<Option<ExitCode>, IntcodeError> {
+		let ip = self.ip;
+		let ins = Instruction::parse(ip, &self.memory)?;
+		let operands: Result<Vec<i128>, i128> = ins
+			.modes()
+			.iter()
+			.enumerate()
+			.map(|(n, mode)| {
+				let i = ip + n as u128 + 1;
+				if ins.opcode.output_parameter() == Some(n) {
+					self.memory.get_address(i, *mode).map(|address| address as i128)
+				}
+				else {
+					self.memory.get(i, *mode)
+				}
+			})
+			.collect();
+		let instruction = self.memory.cell(ip);
+		let input = self.input.front().copied();
+
+		let exit = self.step()?;
+		if exit == Some(ExitCode::Halt) || exit == Some(ExitCode::Wait) {
+			return Ok(exit);
+		}
+
+		let operands = operands.unwrap_or_default();
+		let write = ins.opcode.output_parameter().map(|n| {
+			let address = operands[n] as u128;
+			(address, self.memory.cell(address))
+		});
+		tracer.record(&Record {
+			ip,
+			instruction,
+			write,
+			base: Some(self.memory.base).filter(|_| ins.opcode == Operation::RelativeBase),
+			input: input.filter(|_| ins.opcode == Operation::Input),
+			output: self.output.last().copied().filter(|_| ins.opcode == Operation::Output),
+			operands,
+		});
+		Ok(exit)
+	}
+
+	/// Like `run`, also handing every executed instruction to `tracer`.
+	pub fn run_traced(&mut self, tracer: &mut dyn Tracer) -> Result<ExitCode, IntcodeError> {
+		loop {
+			if let Some(exit) = self.step_traced(tracer)? {
+				return Ok(exit);
+			}
+		}
+	}
+}
+
+////////////////////////////////////////
+// DIFF AND REPLAY
+////////////////////////////////////////
+
+/// First step at which two traces disagree. A missing record means that
+/// trace ended earlier.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Divergence {
+	pub step: usize,
+	pub expected: Option<Record>,
+	pub found: Option<Record>,
+	/// The fault that ended a replayed trace there, if one did.
+	pub error: Option<IntcodeError>,
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let show = |record: &Option<Record>| {
+			record
+				.as_ref()
+				.map_or(String::from("<end of trace>"), |r| r.to_string())
+		};
+		write!(
+			f,
+			"traces diverge at step {}\n  expected: {}\n  found:    {}",
+			self.step,
+			show(&self.expected),
+			show(&self.found)
+		)?;
+		if let Some(error) = &self.error {
+			write!(f, "\n  error:    {}", error)?;
+		}
+		Ok(())
+	}
+}
+
+pub fn diff(expected: &[Record], found: &[Record]) -> Option<Divergence> {
+	first_difference(expected, found).map(|(step, expected, found)| Divergence {
+		step,
+		expected,
+		found,
+		error: None,
+	})
+}
+
+/// Index of the first entry two sequences disagree on, with what each has
+/// there. `None` past the end of one of them.
+pub(crate) fn first_difference<T: PartialEq + Clone>(
+	expected: &[T],
+	found: &[T],
+) -> Option<(usize, Option<T>, Option<T>)> {
+	(0..expected.len().max(found.len()))
+		.find(|i| expected.get(*i) != found.get(*i))
+		.map(|i| (i, expected.get(i).cloned(), found.get(i).cloned()))
+}
+
+/// Runs `machine` feeding it the inputs found in `records`, checking that
+/// it executes exactly the recorded instructions. A fault is a divergence
+/// even after the last record.
+pub fn replay(machine: &mut Machine, records: &[Record]) -> Option<Divergence> {
+	machine.input.extend(records.iter().filter_map(|r| r.input));
+	let mut found = Vec::new();
+	let mut error = None;
+	while found.len() <= records.len() {
+		match machine.step_traced(&mut found) {
+			Ok(None) | Ok(Some(ExitCode::Print)) => {},
+			Ok(_) => break,
+			Err(fault) => {
+				error = Some(fault);
+				break;
+			},
+		}
+		let step = found.len() - 1;
+		if records.get(step) != found.get(step) {
+			break;
+		}
+	}
+	match diff(records, &found) {
+		Some(divergence) => Some(Divergence { error, ..divergence }),
+		None => error.map(|error| Divergence {
+			step: found.len(),
+			expected: None,
+			found: None,
+			error: Some(error),
+		}),
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn program() -> Vec<i128> {
+		vec![3, 13, 109, 5, 21001, 8, 2, 0, 4, 5, 99, 0, 0, 0]
+	}
+
+	#[test]
+	fn trace_test1() {
+		let mut machine = Machine::new(program());
+		machine.input.push_back(7);
+		let mut writer = TraceWriter::new(Vec::new());
+		machine.run_traced(&mut writer).unwrap();
+		machine.run_traced(&mut writer).unwrap();
+		let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+		assert_eq!(
+			"0 3 13 w:13=7 in:7\n2 109 5 rb:5\n4 21001 4,2,5 w:5=6\n8 4 6 out:6\n",
+			text
+		);
+		let records = read_trace(text.as_bytes()).unwrap();
+		assert_eq!(4, records.len());
+		assert_eq!(text, records.iter().map(|r| format!("{}\n", r)).collect::<String>());
+	}
+
+	#[test]
+	fn trace_test2() {
+		let mut records = Vec::new();
+		let mut machine = Machine::new(program());
+		machine.input.push_back(7);
+		while machine.run_traced(&mut records).unwrap() != ExitCode::Halt {}
+		assert_eq!(None, replay(&mut Machine::new(program()), &records));
+
+		let mut patched = program();
+		patched[6] = 3;
+		let divergence = replay(&mut Machine::new(patched), &records).unwrap();
+		assert_eq!(2, divergence.step);
+		assert_eq!(Some((5, 7)), divergence.found.unwrap().write);
+	}
+
+	#[test]
+	fn trace_test3() {
+		let a: Vec<Record> = vec!["0 104 1 out:1".parse().unwrap(), "2 99 -".parse().unwrap()];
+		assert_eq!(None, diff(&a, &a));
+		let divergence = diff(&a, &a[..1]).unwrap();
+		assert_eq!((1, None), (divergence.step, divergence.found));
+		assert_eq!(None, divergence.error);
+		assert!("0 104".parse::<Record>().is_err());
+		assert!("0 104 1 x:1".parse::<Record>().is_err());
+	}
+
+	#[test]
+	fn trace_test4() {
+		let records: Vec<Record> = vec!["0 104 1 out:1".parse().unwrap(), "2 104 2 out:2".parse().unwrap()];
+		let divergence = replay(&mut Machine::new(vec![104, 1, 98]), &records).unwrap();
+		assert_eq!((1, None), (divergence.step, divergence.found.clone()));
+		assert!(matches!(
+			divergence.error,
+			Some(IntcodeError::UnknownOpcode { ip: 2, .. })
+		));
+		assert_eq!(
+			concat!(
+				"traces diverge at step 1\n  expected: 2 104 2 out:2\n  found:    <end of trace>\n",
+				"  error:    unknown opcode 98 at 2 (memory: [98, 0, 0, 0])"
+			),
+			divergence.to_string()
+		);
+
+		// Faulting where the trace ends
+		let divergence = replay(&mut Machine::new(vec![104, 1, 98]), &records[..1]).unwrap();
+		assert_eq!(
+			(1, None, None),
+			(divergence.step, divergence.expected, divergence.found)
+		);
+		assert!(divergence.error.is_some());
+	}
+}
@@ -0,0 +1,351 @@
+//! Recording of what a program read and printed, one event per line:
+//!
+//! ```text
+//! in <step> <value>
+//! out <step> <value>
+//! wait <step>
+//! halt <step>
+//! ```
+//!
+//! `step` is the number of instructions the machine had executed right
+//! after the event (see `Machine::executed`). `wait` marks the program
+//! asking for an input nobody had given yet, so the inputs after it are
+//! the ones typed in answer.
+
+use super::trace::{first_difference, read_lines};
+use super::{ExitCode, IntcodeError, Machine};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+////////////////////////////////////////
+// EVENT
+////////////////////////////////////////
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Event {
+	Input { step: u64, value: i128 },
+	Output { step: u64, value: i128 },
+	Wait { step: u64 },
+	Halt { step: u64 },
+}
+
+impl Event {
+	pub fn step(&self) -> u64 {
+		match self {
+			Event::Input { step, .. } | Event::Output { step, .. } => *step,
+			Event::Wait { step } | Event::Halt { step } => *step,
+		}
+	}
+}
+
+impl fmt::Display for Event {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Event::Input { step, value } => write!(f, "in {} {}", step, value),
+			Event::Output { step, value } => write!(f, "out {} {}", step, value),
+			Event::Wait { step } => write!(f, "wait {}", step),
+			Event::Halt { step } => write!(f, "halt {}", step),
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseEventError(pub String);
+
+impl fmt::Display for ParseEventError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid transcript event `{}`", self.0)
+	}
+}
+
+impl Error for ParseEventError {}
+
+impl FromStr for Event {
+	type Err = ParseEventError;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		let invalid = || ParseEventError(line.to_string());
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		let step = || fields.get(1).and_then(|s| s.parse().ok()).ok_or_else(invalid);
+		let value = || fields.get(2).and_then(|v| v.parse().ok()).ok_or_else(invalid);
+		let event = match (fields.first(), fields.len()) {
+			(Some(&"in"), 3) => Event::Input {
+				step: step()?,
+				value: value()?,
+			},
+			(Some(&"out"), 3) => Event::Output {
+				step: step()?,
+				value: value()?,
+			},
+			(Some(&"wait"), 2) => Event::Wait { step: step()? },
+			(Some(&"halt"), 2) => Event::Halt { step: step()? },
+			_ => return Err(invalid()),
+		};
+		Ok(event)
+	}
+}
+
+pub fn read_transcript<R: BufRead>(reader: R) -> Result<Vec<Event>, Box<dyn Error>> {
+	read_lines(reader)
+}
+
+pub fn write_transcript<W: Write>(mut writer: W, events: &[Event]) -> io::Result<()> {
+	for event in events {
+		writeln!(writer, "{}", event)?;
+	}
+	writer.flush()
+}
+
+////////////////////////////////////////
+// RECORDING
+////////////////////////////////////////
+
+impl Machine {
+	/// Like `step`, also adding what the instruction read or printed to
+	/// `events`. Stopping again at the same `wait` or `halt` is only
+	/// recorded once.
+	pub fn step_transcribed(&mut self, events: &mut Vec<Event>) -> Result<Option<ExitCode>, IntcodeError> {
+		let input = self.input.front().copied();
+		let (inputs, outputs) = (self.input.len(), self.output.len());
+		let exit = self.step()?;
+		let step = self.executed;
+		let event = match exit {
+			Some(ExitCode::Wait) => Some(Event::Wait { step }),
+			Some(ExitCode::Halt) => Some(Event::Halt { step }),
+			_ if self.input.len() < inputs => input.map(|value| Event::Input { step, value }),
+			_ if self.output.len() > outputs => self.output.last().map(|value| Event::Output { step, value: *value }),
+			_ => None,
+		};
+		if let Some(event) = event.filter(|event| events.last() != Some(event)) {
+			events.push(event);
+		}
+		Ok(exit)
+	}
+
+	/// Like `run`, also adding what the program read and printed to
+	/// `events`.
+	pub fn run_transcribed(&mut self, events: &mut Vec<Event>) -> Result<ExitCode, IntcodeError> {
+		loop {
+			if let Some(exit) = self.step_transcribed(events)? {
+				return Ok(exit);
+			}
+		}
+	}
+}
+
+////////////////////////////////////////
+// DIFF AND REPLAY
+////////////////////////////////////////
+
+/// First event two transcripts disagree on. A missing event means that
+/// transcript ended earlier, or that the program got past the step of the
+/// expected event without doing it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Divergence {
+	pub index: usize,
+	pub expected: Option<Event>,
+	pub found: Option<Event>,
+	/// The fault that ended a replayed transcript there, if one did.
+	pub error: Option<IntcodeError>,
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let show = |event: &Option<Event>| event.map_or(String::from("<nothing>"), |e| e.to_string());
+		write!(
+			f,
+			"transcripts diverge at event {}\n  expected: {}\n  found:    {}",
+			self.index,
+			show(&self.expected),
+			show(&self.found)
+		)?;
+		if let Some(error) = &self.error {
+			write!(f, "\n  error:    {}", error)?;
+		}
+		Ok(())
+	}
+}
+
+pub fn diff(expected: &[Event], found: &[Event]) -> Option<Divergence> {
+	first_difference(expected, found).map(|(index, expected, found)| Divergence {
+		index,
+		expected,
+		found,
+		error: None,
+	})
+}
+
+/// Queues the inputs from `from` up to the next `wait`. Returns how many.
+fn feed(machine: &mut Machine, events: &[Event], from: usize) -> usize {
+	let inputs = events
+		.get(from..)
+		.unwrap_or_default()
+		.iter()
+		.take_while(|event| !matches!(event, Event::Wait { .. }))
+		.filter_map(|event| match event {
+			Event::Input { value, .. } => Some(*value),
+			_ => None,
+		});
+	let before = machine.input.len();
+	machine.input.extend(inputs);
+	machine.input.len() - before
+}
+
+/// Runs `machine` giving it the recorded inputs, each batch when the
+/// program asks for it, and checks that it reads and prints exactly what
+/// was recorded, at the same steps. A fault before the last event comes
+/// with the divergence it causes.
+pub fn replay(machine: &mut Machine, events: &[Event]) -> Option<Divergence> {
+	let mut found = Vec::new();
+	let mut error = None;
+	feed(machine, events, 0);
+	while let Some(expected) = events.get(found.len()) {
+		if machine.executed > expected.step() {
+			break;
+		}
+		let exit = machine.step_transcribed(&mut found);
+		if found
+			.last()
+			.is_some_and(|event| events.get(found.len() - 1) != Some(event))
+		{
+			break;
+		}
+		match exit {
+			Ok(Some(ExitCode::Wait)) if feed(machine, events, found.len()) == 0 => break,
+			Ok(Some(ExitCode::Halt)) => break,
+			Err(fault) => {
+				error = Some(fault);
+				break;
+			},
+			_ => {},
+		}
+	}
+	diff(events, &found).map(|divergence| Divergence { error, ..divergence })
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::ascii::AsciiMachine;
+	use super::super::fixtures::adder;
+	use super::*;
+
+	fn session() -> Vec<Event> {
+		let mut events = Vec::new();
+		let mut machine = Machine::new(adder());
+		machine.input.extend(vec![1, 2]);
+		assert_eq!(Ok(ExitCode::Print), machine.run_transcribed(&mut events));
+		while machine.run_transcribed(&mut events) == Ok(ExitCode::Print) {}
+		machine.input.push_back(0);
+		while machine.run_transcribed(&mut events) == Ok(ExitCode::Print) {}
+		// Halting again adds nothing
+		machine.run_transcribed(&mut events).unwrap();
+		events
+	}
+
+	#[test]
+	fn transcript_test1() {
+		let events = session();
+		let mut text = Vec::new();
+		write_transcript(&mut text, &events).unwrap();
+		let text = String::from_utf8(text).unwrap();
+		assert_eq!("in 1 1\nout 4 1\nin 6 2\nout 9 3\nwait 10\nin 11 0\nhalt 12\n", text);
+		assert_eq!(events, read_transcript(text.as_bytes()).unwrap());
+		assert!("in 1".parse::<Event>().is_err());
+		assert!("out 1 2 3".parse::<Event>().is_err());
+		assert!("halt -1".parse::<Event>().is_err());
+		assert!("print 1 2".parse::<Event>().is_err());
+	}
+
+	#[test]
+	fn transcript_test2() {
+		let events = session();
+		assert_eq!(None, replay(&mut Machine::new(adder()), &events));
+
+		// Multiplying instead of adding
+		let mut patched = adder();
+		patched[5] = 2;
+		let divergence = replay(&mut Machine::new(patched), &events).unwrap();
+		assert_eq!(1, divergence.index);
+		assert_eq!(Some(Event::Output { step: 4, value: 0 }), divergence.found);
+		assert_eq!(
+			"transcripts diverge at event 1\n  expected: out 4 1\n  found:    out 4 0",
+			divergence.to_string()
+		);
+
+		// Never printing anything is caught at the step the output was due
+		let events = vec![Event::Output { step: 1, value: 5 }, Event::Halt { step: 1 }];
+		assert_eq!(None, replay(&mut Machine::new(vec![104, 5, 99]), &events));
+		let divergence = replay(&mut Machine::new(vec![1105, 1, 0]), &events).unwrap();
+		assert_eq!((0, None), (divergence.index, divergence.found));
+		assert_eq!(
+			Some(Divergence {
+				index: 1,
+				expected: Some(Event::Halt { step: 1 }),
+				found: None,
+				error: Some(IntcodeError::UnknownOpcode {
+					ip: 2,
+					value: 98,
+					excerpt: vec![98, 0, 0, 0],
+				}),
+			}),
+			replay(&mut Machine::new(vec![104, 5, 98]), &events)
+		);
+
+		// The second batch of inputs is only given after the program waits
+		let early = vec![Event::Input { step: 1, value: 0 }, Event::Halt { step: 2 }];
+		assert_eq!(None, replay(&mut Machine::new(adder()), &early));
+		let late = vec![
+			Event::Wait { step: 0 },
+			Event::Input { step: 1, value: 0 },
+			Event::Halt { step: 2 },
+		];
+		assert_eq!(None, replay(&mut Machine::new(adder()), &late));
+		assert_eq!(0, replay(&mut Machine::new(vec![104, 0, 99]), &late).unwrap().index);
+	}
+
+	#[test]
+	fn transcript_test3() {
+		// Says hi, then echoes one line
+		let code = vec![
+			104, 104, 104, 105, 104, 10, 3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 6, 99,
+		];
+		let mut ascii = AsciiMachine::new(Machine::new(code.clone()));
+		ascii.transcript = Some(Vec::new());
+		assert_eq!("hi\n", ascii.run().unwrap().text);
+		ascii.send("yo").unwrap();
+		assert_eq!("yo\n", ascii.run().unwrap().text);
+		let events = ascii.transcript.unwrap();
+		assert_eq!(Some(&Event::Wait { step: 3 }), events.get(3));
+		assert_eq!(Some(&Event::Halt { step: 15 }), events.last());
+		assert_eq!(None, replay(&mut Machine::new(code.clone()), &events));
+
+		let mut exclaiming = code;
+		exclaiming[8] = 104;
+		exclaiming[9] = 33;
+		let divergence = replay(&mut Machine::new(exclaiming), &events).unwrap();
+		assert_eq!(Some(Event::Output { step: 5, value: 33 }), divergence.found);
+	}
+
+	#[test]
+	fn transcript_test4() {
+		// Faults instead of printing
+		let events = vec![Event::Output { step: 1, value: 5 }, Event::Halt { step: 1 }];
+		let divergence = replay(&mut Machine::new(vec![98]), &events).unwrap();
+		assert_eq!((0, None), (divergence.index, divergence.found));
+		assert_eq!(
+			concat!(
+				"transcripts diverge at event 0\n  expected: out 1 5\n  found:    <nothing>\n",
+				"  error:    unknown opcode 98 at 0 (memory: [98, 0, 0, 0])"
+			),
+			divergence.to_string()
+		);
+		assert_eq!(None, diff(&events, &events));
+	}
+}
@@ -0,0 +1,519 @@
+//! Ahead-of-time translation of a program into Rust source.
+//!
+//! The generated module has a function per basic block (see `cfg`), cut
+//! further so that blocks start at every input and right after every output,
+//! where the machine may stop and resume. Before a block runs, its words are
+//! checked against the program it was translated from. Anything that has
+//! been modified since, or was never found as code, is run by the
+//! interpreter, one instruction at a time, until a block is reached again.
+//!
+//! Generated code calls back into this module for the parts that can fail,
+//! so faults are reported exactly as `Machine::run` would report them.
+
+use super::cfg::{self, Block};
+use super::disassembler::Line;
+use super::{IntcodeError, Machine, Operation, ParameterMode};
+use std::fmt::Write;
+
+////////////////////////////////////////
+// RUNTIME
+////////////////////////////////////////
+
+/// `value` as an address, for the instruction at `ip`.
+pub fn address(machine: &Machine, ip: u128, value: i128) -> Result<u128, IntcodeError> {
+	if value < 0 {
+		return Err(IntcodeError::NegativeAddress {
+			ip,
+			address: value,
+			excerpt: machine.memory.excerpt(ip),
+		});
+	}
+	Ok(value as u128)
+}
+
+pub fn add(machine: &Machine, ip: u128, a: i128, b: i128) -> Result<i128, IntcodeError> {
+	machine
+		.arithmetic
+		.add(&a, &b)
+		.ok_or_else(|| overflow(machine, ip, Operation::Add, a, b))
+}
+
+pub fn mul(machine: &Machine, ip: u128, a: i128, b: i128) -> Result<i128, IntcodeError> {
+	machine
+		.arithmetic
+		.mul(&a, &b)
+		.ok_or_else(|| overflow(machine, ip, Operation::Mul, a, b))
+}
+
+fn overflow(machine: &Machine, ip: u128, operation: Operation, a: i128, b: i128) -> IntcodeError {
+	IntcodeError::Overflow {
+		ip,
+		operation,
+		operands: [a, b],
+		excerpt: machine.memory.excerpt(ip),
+	}
+}
+
+////////////////////////////////////////
+// SEGMENTS
+////////////////////////////////////////
+
+/// A run of instructions translated into one function.
+struct Segment<'a> {
+	lines: Vec<&'a Line>,
+}
+
+impl Segment<'_> {
+	fn start(&self) -> usize {
+		self.lines[0].address()
+	}
+
+	fn end(&self) -> usize {
+		let last = self.lines[self.lines.len() - 1];
+		last.address() + last.words().len()
+	}
+}
+
+/// Whether every operand read or written through a fixed address has a
+/// valid one. Anything else is left to the interpreter to report.
+fn translatable(line: &Line) -> bool {
+	match line {
+		Line::Instruction { modes, words, .. } => modes
+			.iter()
+			.zip(&words[1..])
+			.all(|(mode, word)| *mode != ParameterMode::Position || *word >= 0),
+		Line::Data { .. } => false,
+	}
+}
+
+/// The address an instruction writes to, when it is a fixed one.
+fn fixed_write(line: &Line) -> Option<usize> {
+	match line {
+		Line::Instruction {
+			operation,
+			modes,
+			words,
+			..
+		} => {
+			let n = operation.output_parameter()?;
+			if modes[n] == ParameterMode::Position {
+				Some(words[n + 1] as usize)
+			}
+			else {
+				None
+			}
+		},
+		Line::Data { .. } => None,
+	}
+}
+
+fn operation(line: &Line) -> Operation {
+	match line {
+		Line::Instruction { operation, .. } => *operation,
+		Line::Data { .. } => unreachable!(),
+	}
+}
+
+fn segments(block: &Block) -> Vec<Segment<'_>> {
+	let end = block
+		.lines
+		.last()
+		.map(|line| line.address() + line.words().len())
+		.unwrap_or(0);
+	let mut segments = Vec::new();
+	let mut current = Vec::new();
+	for line in block.lines.iter().take_while(|line| translatable(line)) {
+		if operation(line) == Operation::Input && !current.is_empty() {
+			segments.push(Segment { lines: current });
+			current = Vec::new();
+		}
+		current.push(line);
+		let next = line.address() + line.words().len();
+		let rewrites_block = fixed_write(line).is_some_and(|address| (next..end).contains(&address));
+		if operation(line) == Operation::Output || rewrites_block {
+			segments.push(Segment { lines: current });
+			current = Vec::new();
+		}
+	}
+	if !current.is_empty() {
+		segments.push(Segment { lines: current });
+	}
+	segments
+}
+
+////////////////////////////////////////
+// TRANSLATION
+////////////////////////////////////////
+
+/// Reads operand `n` of `line`, as an expression.
+fn read(line: &Line, n: usize) -> String {
+	let (modes, words) = match line {
+		Line::Instruction { modes, words, .. } => (modes, words),
+		Line::Data { .. } => unreachable!(),
+	};
+	let word = words[n + 1];
+	match modes[n] {
+		ParameterMode::Immediate => word.to_string(),
+		ParameterMode::Position => format!("m.memory.cell({})", word),
+		ParameterMode::Relative => format!("m.memory.cell({})", relative(line.address(), word)),
+	}
+}
+
+fn relative(ip: usize, offset: i128) -> String {
+	format!("address(m, {}, m.memory.base.wrapping_add({}))?", ip, offset)
+}
+
+/// Writes `v` where operand `n` of `line` points, leaving the block if that
+/// may have been one of its own instructions further on.
+fn write(out: &mut String, line: &Line, n: usize, segment_end: usize) {
+	let (modes, words) = match line {
+		Line::Instruction { modes, words, .. } => (modes, words),
+		Line::Data { .. } => unreachable!(),
+	};
+	let next = line.address() + words.len();
+	match modes[n] {
+		ParameterMode::Relative => {
+			writeln!(out, "\tlet t = {};", relative(line.address(), words[n + 1])).unwrap();
+			writeln!(out, "\tm.memory.set(t, v);").unwrap();
+			if next < segment_end {
+				writeln!(out, "\tif ({}..{}).contains(&t) {{", next, segment_end).unwrap();
+				writeln!(out, "\t\tm.executed += 1;").unwrap();
+				writeln!(out, "\t\tm.ip = {};", next).unwrap();
+				writeln!(out, "\t\treturn Ok(None);").unwrap();
+				writeln!(out, "\t}}").unwrap();
+			}
+		},
+		_ => writeln!(out, "\tm.memory.set({}, v);", words[n + 1]).unwrap(),
+	}
+}
+
+/// The body of one block function. Every instruction first points `ip` at
+/// itself, so a fault leaves the machine where the interpreter would.
+fn translate_segment(out: &mut String, segment: &Segment) {
+	let end = segment.end();
+	writeln!(out, "/// {}..{}", segment.start(), end).unwrap();
+	writeln!(
+		out,
+		"fn block_{}(m: &mut Machine) -> Result<Option<ExitCode>, IntcodeError> {{",
+		segment.start()
+	)
+	.unwrap();
+	for line in &segment.lines {
+		let ip = line.address();
+		let next = ip + line.words().len();
+		writeln!(out, "\t// {}  {}", ip, line.text()).unwrap();
+		writeln!(out, "\tm.ip = {};", ip).unwrap();
+		match operation(line) {
+			Operation::Add | Operation::Mul => {
+				let function = match operation(line) {
+					Operation::Add => "add",
+					_ => "mul",
+				};
+				writeln!(out, "\tlet a = {};", read(line, 0)).unwrap();
+				writeln!(out, "\tlet b = {};", read(line, 1)).unwrap();
+				writeln!(out, "\tlet v = {}(m, {}, a, b)?;", function, ip).unwrap();
+				write(out, line, 2, end);
+			},
+			Operation::LessThan | Operation::Equals => {
+				let comparison = match operation(line) {
+					Operation::LessThan => "<",
+					_ => "==",
+				};
+				writeln!(out, "\tlet a = {};", read(line, 0)).unwrap();
+				writeln!(out, "\tlet b = {};", read(line, 1)).unwrap();
+				writeln!(out, "\tlet v = (a {} b) as i128;", comparison).unwrap();
+				write(out, line, 2, end);
+			},
+			Operation::Input => {
+				writeln!(out, "\tlet v = match m.input.pop_front() {{").unwrap();
+				writeln!(out, "\t\tSome(v) => v,").unwrap();
+				writeln!(out, "\t\tNone => return Ok(Some(ExitCode::Wait)),").unwrap();
+				writeln!(out, "\t}};").unwrap();
+				write(out, line, 0, end);
+			},
+			Operation::Output => {
+				writeln!(out, "\tlet a = {};", read(line, 0)).unwrap();
+				writeln!(out, "\tm.output.push(a);").unwrap();
+				writeln!(out, "\tm.executed += 1;").unwrap();
+				writeln!(out, "\tm.ip = {};", next).unwrap();
+				writeln!(out, "\tOk(Some(ExitCode::Print))\n}}\n").unwrap();
+				return;
+			},
+			Operation::JumpIfTrue | Operation::JumpIfFalse => {
+				let comparison = if operation(line) == Operation::JumpIfTrue {
+					"!="
+				}
+				else {
+					"=="
+				};
+				writeln!(out, "\tlet a = {};", read(line, 0)).unwrap();
+				writeln!(out, "\tif a {} 0 {{", comparison).unwrap();
+				writeln!(out, "\t\tlet target = address(m, {}, {})?;", ip, read(line, 1)).unwrap();
+				writeln!(out, "\t\tm.executed += 1;").unwrap();
+				writeln!(out, "\t\tm.ip = target;").unwrap();
+				writeln!(out, "\t\treturn Ok(None);").unwrap();
+				writeln!(out, "\t}}").unwrap();
+			},
+			Operation::RelativeBase => {
+				writeln!(out, "\tlet a = {};", read(line, 0)).unwrap();
+				writeln!(out, "\tm.memory.add_to_base(a);").unwrap();
+			},
+			Operation::Halt => {
+				writeln!(out, "\tOk(Some(ExitCode::Halt))\n}}\n").unwrap();
+				return;
+			},
+		}
+		writeln!(out, "\tm.executed += 1;").unwrap();
+	}
+	writeln!(out, "\tm.ip = {};", end).unwrap();
+	writeln!(out, "\tOk(None)\n}}\n").unwrap();
+}
+
+/// A Rust module running `code`. It reaches the `intcode` module through
+/// `runtime`, e.g. `advent_of_code_2019::intcode`, and provides:
+///
+/// - `CODE`, the program it was translated from,
+/// - `machine()`, a machine loaded with it,
+/// - `run` and `run_until_halt`, which behave like the methods of the same
+///   name. Machines with `limits` are handed to the interpreter instead.
+pub fn translate(code: &[i128], runtime: &str) -> String {
+	let graph = cfg::build(code);
+	let segments: Vec<Segment> = graph.blocks.values().flat_map(segments).collect();
+
+	let mut blocks = String::new();
+	for segment in &segments {
+		translate_segment(&mut blocks, segment);
+	}
+	blocks.pop();
+	let helpers: Vec<&str> = ["add", "address", "mul"]
+		.iter()
+		.copied()
+		.filter(|helper| blocks.contains(&format!("{}(m, ", helper)))
+		.collect();
+
+	let mut out = String::new();
+	writeln!(out, "//! Generated by intcode-aot, do not edit.\n").unwrap();
+	match helpers.len() {
+		0 => {},
+		1 => writeln!(out, "use {}::translate::{};", runtime, helpers[0]).unwrap(),
+		_ => writeln!(out, "use {}::translate::{{{}}};", runtime, helpers.join(", ")).unwrap(),
+	}
+	writeln!(out, "use {}::{{ExitCode, IntcodeError, Limits, Machine}};\n", runtime).unwrap();
+	let words: Vec<String> = code.iter().map(|word| word.to_string()).collect();
+	writeln!(
+		out,
+		"pub static CODE: [i128; {}] = [{}];\n",
+		code.len(),
+		words.join(", ")
+	)
+	.unwrap();
+
+	out += "pub fn machine() -> Machine {
+	Machine::new(CODE.to_vec())
+}
+
+/// Whether memory from `start` to `end` still holds the translated code.
+fn intact(m: &Machine, start: usize, end: usize) -> bool {
+	(start..end).all(|address| m.memory.cell(address as u128) == CODE[address])
+}
+
+pub fn run(m: &mut Machine) -> Result<ExitCode, IntcodeError> {
+	if m.limits != Limits::default() {
+		return m.run();
+	}
+	loop {
+		let exit = match m.ip {
+";
+	for segment in &segments {
+		writeln!(
+			out,
+			"\t\t\t{0} if intact(m, {0}, {1}) => block_{0}(m)?,",
+			segment.start(),
+			segment.end()
+		)
+		.unwrap();
+	}
+	out += "			_ => m.step()?,
+		};
+		if let Some(exit) = exit {
+			return Ok(exit);
+		}
+	}
+}
+
+pub fn run_until_halt(m: &mut Machine) -> Result<(), IntcodeError> {
+	loop {
+		match run(m)? {
+			ExitCode::Halt => return Ok(()),
+			ExitCode::Wait => {
+				return Err(IntcodeError::InputExhausted {
+					ip: m.ip,
+					excerpt: m.memory.excerpt(m.ip),
+				})
+			},
+			ExitCode::Print => {},
+		}
+	}
+}
+
+";
+	out + &blocks
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::assembler::assemble;
+	use super::super::parse_program;
+	use super::*;
+	use std::fs;
+	use std::process::Command;
+
+	#[test]
+	fn translate_test1() {
+		let code = assemble(
+			"
+			loop: in [rb+10]
+			      out [rb+10]
+			      arb #1
+			      jt #1, #loop
+			",
+		)
+		.unwrap();
+		let module = translate(&code, "crate::intcode");
+		assert!(module.contains("use crate::intcode::{ExitCode, IntcodeError, Limits, Machine};\n"));
+		assert!(module.contains("\t\t\t0 if intact(m, 0, 4) => block_0(m)?,\n"));
+		assert!(module.contains("\t\t\t4 if intact(m, 4, 9) => block_4(m)?,\n"));
+		assert!(module.contains("\t// 0  in [rb+10]\n\tm.ip = 0;\n\tlet v = match m.input.pop_front() {\n"));
+		assert!(module.contains("\tm.ip = 4;\n\tOk(Some(ExitCode::Print))\n}\n"));
+		assert!(module.ends_with("\tm.ip = 9;\n\tOk(None)\n}\n"));
+	}
+
+	/// Every program from the day 5 and day 9 tests, a few that modify
+	/// themselves, and the puzzle inputs, with their inputs.
+	fn vectors() -> Vec<(Vec<i128>, Vec<i128>)> {
+		let compare = vec![
+			3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125,
+			20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+		];
+		let rewrite = assemble(
+			"
+			start:  out #7
+			        jt [flag], #end
+			        add #1, #0, [flag]
+			        add #4, #0, [start]
+			        jf #0, #start
+			end:    hlt
+			flag:   .data 0
+			",
+		)
+		.unwrap();
+		let day5 = parse_program(&fs::read_to_string("input/day5/input1.txt").unwrap()).unwrap();
+		let day9 = parse_program(&fs::read_to_string("input/day9/input1.txt").unwrap()).unwrap();
+		let mut vectors = vec![
+			(vec![1, 0, 0, 0, 99], vec![]),
+			(vec![2, 3, 0, 3, 99], vec![]),
+			(vec![2, 4, 4, 5, 99, 0], vec![]),
+			(vec![1, 1, 1, 4, 99, 5, 6, 0, 99], vec![]),
+			(vec![1002, 4, 3, 4, 33], vec![]),
+			(
+				vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99],
+				vec![],
+			),
+			(vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0], vec![]),
+			(vec![104, 1125899906842624, 99], vec![]),
+			(rewrite, vec![]),
+			// Faults, and running out of input
+			(vec![1, 0, 0, 3, 1, 1, 1, -1, 99], vec![]),
+			(vec![1, 0, 0, 0, 3, 0, 4, 0, 3, 1, 99], vec![5]),
+			(vec![109, -5, 1201, 0, 1, 0, 99], vec![]),
+		];
+		for (code, inputs) in [
+			(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], vec![7, 8]),
+			(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], vec![7, 9]),
+			(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], vec![7, 8]),
+			(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], vec![7, 9]),
+			(
+				vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+				vec![0, 1],
+			),
+			(vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], vec![0, 1]),
+			(compare, vec![7, 8, 9]),
+			(day5, vec![1, 5]),
+			(day9, vec![1, 2]),
+		] {
+			for input in inputs {
+				vectors.push((code.clone(), vec![input]));
+			}
+		}
+		vectors
+	}
+
+	fn outcome(result: Result<(), IntcodeError>, machine: &Machine) -> String {
+		format!(
+			"{:?} {:?} {} {:?}",
+			result,
+			machine.output,
+			machine.executed,
+			machine.memory.to_vec()
+		)
+	}
+
+	#[test]
+	fn translate_test2() {
+		let vectors = vectors();
+		let dir = std::env::temp_dir().join(format!("intcode-translate-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let mut main = format!(
+			"#![allow(dead_code)]\n\n#[path = \"{}/src/intcode/mod.rs\"]\nmod intcode;\n",
+			env!("CARGO_MANIFEST_DIR")
+		);
+		main += "\nuse intcode::{IntcodeError, Machine};\n";
+		for (n, (code, _)) in vectors.iter().enumerate() {
+			fs::write(dir.join(format!("p{}.rs", n)), translate(code, "crate::intcode")).unwrap();
+			main += &format!("mod p{};\n", n);
+		}
+		main += "\nfn outcome(result: Result<(), IntcodeError>, machine: &Machine) -> String {
+	format!(\"{:?} {:?} {} {:?}\", result, machine.output, machine.executed, machine.memory.to_vec())
+}
+
+fn main() {
+";
+		for (n, (_, inputs)) in vectors.iter().enumerate() {
+			main += &format!(
+				"\tlet mut m = p{0}::machine();\n\tm.input.extend::<Vec<i128>>(vec!{1:?});\n\tprintln!(\"{{}}\", outcome(p{0}::run_until_halt(&mut m), &m));\n",
+				n, inputs
+			);
+		}
+		main += "}\n";
+		fs::write(dir.join("main.rs"), main).unwrap();
+
+		let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+		let compiled = Command::new(rustc)
+			.current_dir(&dir)
+			.args(["--edition", "2018", "-D", "warnings", "-o", "main", "main.rs"])
+			.output()
+			.unwrap();
+		assert!(
+			compiled.status.success(),
+			"{}",
+			String::from_utf8_lossy(&compiled.stderr)
+		);
+		let ran = Command::new(dir.join("main")).output().unwrap();
+		assert!(ran.status.success());
+		let translated = String::from_utf8(ran.stdout).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(vectors.len(), translated.lines().count());
+		for ((code, inputs), translated) in vectors.into_iter().zip(translated.lines()) {
+			let mut machine = Machine::new(code);
+			machine.input.extend(inputs);
+			let result = machine.run_until_halt();
+			assert_eq!(outcome(result, &machine), translated);
+		}
+	}
+}
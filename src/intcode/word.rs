@@ -0,0 +1,165 @@
+//! What a memory cell holds. Small words run faster, `BigInt` never
+//! overflows.
+
+use std::fmt;
+use std::str::FromStr;
+
+pub trait Word: Clone + PartialEq + PartialOrd + Default + fmt::Debug + fmt::Display + FromStr {
+	fn from_i64(n: i64) -> Self;
+
+	/// `None` if it does not fit.
+	fn to_i64(&self) -> Option<i64>;
+
+	/// `None` if it is negative or above `MAX_ADDRESS`.
+	fn to_address(&self) -> Option<u128>;
+
+	/// Wraps around if the word has a fixed size.
+	fn plus(&self, other: &Self) -> Self;
+
+	/// Wraps around if the word has a fixed size.
+	fn times(&self, other: &Self) -> Self;
+
+	fn checked_plus(&self, other: &Self) -> Option<Self>;
+
+	fn checked_times(&self, other: &Self) -> Option<Self>;
+
+	fn saturating_plus(&self, other: &Self) -> Self;
+
+	fn saturating_times(&self, other: &Self) -> Self;
+
+	fn is_zero(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+macro_rules! primitive_word {
+	($($t:ty),*) => {
+		$(
+			impl Word for $t {
+				fn from_i64(n: i64) -> Self {
+					n as $t
+				}
+
+				fn to_i64(&self) -> Option<i64> {
+					std::convert::TryFrom::try_from(*self).ok()
+				}
+
+				fn to_address(&self) -> Option<u128> {
+					std::convert::TryFrom::try_from(*self).ok()
+				}
+
+				fn plus(&self, other: &Self) -> Self {
+					self.wrapping_add(*other)
+				}
+
+				fn times(&self, other: &Self) -> Self {
+					self.wrapping_mul(*other)
+				}
+
+				fn checked_plus(&self, other: &Self) -> Option<Self> {
+					self.checked_add(*other)
+				}
+
+				fn checked_times(&self, other: &Self) -> Option<Self> {
+					self.checked_mul(*other)
+				}
+
+				fn saturating_plus(&self, other: &Self) -> Self {
+					self.saturating_add(*other)
+				}
+
+				fn saturating_times(&self, other: &Self) -> Self {
+					self.saturating_mul(*other)
+				}
+			}
+		)*
+	};
+}
+
+primitive_word!(i64, i128);
+
+////////////////////////////////////////
+// ARITHMETIC
+////////////////////////////////////////
+
+/// What `add` and `mul` do when the result does not fit in a word.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum Arithmetic {
+	Wrapping,
+	/// Stops the machine with `IntcodeError::Overflow`.
+	#[default]
+	Checked,
+	Saturating,
+}
+
+impl Arithmetic {
+	/// `None` if checked arithmetic overflows.
+	pub fn add<W: Word>(self, a: &W, b: &W) -> Option<W> {
+		match self {
+			Arithmetic::Wrapping => Some(a.plus(b)),
+			Arithmetic::Checked => a.checked_plus(b),
+			Arithmetic::Saturating => Some(a.saturating_plus(b)),
+		}
+	}
+
+	/// `None` if checked arithmetic overflows.
+	pub fn mul<W: Word>(self, a: &W, b: &W) -> Option<W> {
+		match self {
+			Arithmetic::Wrapping => Some(a.times(b)),
+			Arithmetic::Checked => a.checked_times(b),
+			Arithmetic::Saturating => Some(a.saturating_times(b)),
+		}
+	}
+}
+
+////////////////////////////////////////
+// TESTS
+////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+	use super::super::bigint::BigInt;
+	use super::super::{parse_words, IntcodeError, Machine};
+	use super::*;
+
+	/// Squares 3 as many times as it is told to, then prints it.
+	const SQUARES: &str = "3,19,1006,19,16,2,20,20,20,1001,19,-1,19,1105,1,2,4,20,99,0,3";
+
+	fn squares<W: Word>(times: i64) -> Result<Vec<W>, IntcodeError<W>> {
+		let mut machine = Machine::<W>::from_code(parse_words(SQUARES).ok().unwrap());
+		machine.input.push_back(W::from_i64(times));
+		machine.run_until_halt()?;
+		Ok(machine.output)
+	}
+
+	#[test]
+	fn word_test1() {
+		assert_eq!(Ok(vec![6561]), squares::<i64>(3));
+		assert_eq!(Ok(vec![6561]), squares::<i128>(3));
+		assert_eq!(Ok(vec![BigInt::from(6561)]), squares::<BigInt>(3));
+		let exact: BigInt = "11790184577738583171520872861412518665678211592275841109096961"
+			.parse()
+			.unwrap();
+		assert_eq!(Ok(vec![exact]), squares::<BigInt>(7));
+	}
+
+	#[test]
+	fn word_test2() {
+		// Addresses that do not fit are as bad as negative ones
+		let huge = BigInt::from(i128::MAX).times(&BigInt::from(4));
+		let mut machine = Machine::from_code(vec![BigInt::from(4), huge.clone(), BigInt::from(99)]);
+		assert!(matches!(machine.run(), Err(IntcodeError::NegativeAddress { address, .. }) if address == huge));
+		let past = BigInt::from(i128::MAX).plus(&BigInt::from(1));
+		assert_eq!(None, past.to_address());
+		let mut machine = Machine::from_code(vec![BigInt::from(1101), BigInt::from(0), BigInt::from(0), past.clone()]);
+		assert!(matches!(machine.run(), Err(IntcodeError::NegativeAddress { address, .. }) if address == past));
+		let mut machine = Machine::from_code(vec![BigInt::from(1105), BigInt::from(1), past]);
+		assert!(matches!(
+			machine.run(),
+			Err(IntcodeError::NegativeAddress { ip: 0, .. })
+		));
+		let mut machine = Machine::from_code(vec![huge.clone()]);
+		assert!(matches!(machine.run(), Err(IntcodeError::UnknownOpcode { value, .. }) if value == huge));
+	}
+}
@@ -0,0 +1,13 @@
+use advent_of_code_2019::intcode::{parse_program, translate};
+use std::env;
+use std::fs::read_to_string;
+
+pub fn main() {
+	let mut args = env::args().skip(1);
+	let path = args.next().expect("usage: intcode-aot <program> [runtime path]");
+	let runtime = args
+		.next()
+		.unwrap_or_else(|| String::from("advent_of_code_2019::intcode"));
+	let code = parse_program(&read_to_string(path).unwrap()).unwrap();
+	print!("{}", translate::translate(&code, &runtime));
+}
@@ -0,0 +1,9 @@
+use advent_of_code_2019::intcode::{cfg, parse_program};
+use std::env;
+use std::fs::read_to_string;
+
+pub fn main() {
+	let path = env::args().nth(1).expect("usage: intcode-cfg <program>");
+	let code = parse_program(&read_to_string(path).unwrap()).unwrap();
+	print!("{}", cfg::build(&code).dot());
+}
@@ -0,0 +1,29 @@
+use advent_of_code_2019::intcode::debugger::Debugger;
+use advent_of_code_2019::intcode::{parse_program, Machine};
+use std::env;
+use std::fs::read_to_string;
+use std::io::{self, BufRead, Write};
+
+pub fn main() {
+	let mut args = env::args().skip(1);
+	let path = args.next().expect("usage: intcode-dbg <program> [inputs...]");
+	let code = parse_program(&read_to_string(path).unwrap()).unwrap();
+	let mut machine = Machine::new(code);
+	machine.input = args.map(|n| n.parse().unwrap()).collect();
+
+	let mut debugger = Debugger::new(machine);
+	println!("{}", debugger.execute("list").unwrap());
+	let stdin = io::stdin();
+	loop {
+		print!("(dbg) ");
+		io::stdout().flush().unwrap();
+		let mut line = String::new();
+		if stdin.lock().read_line(&mut line).unwrap() == 0 {
+			break;
+		}
+		match debugger.execute(&line) {
+			Some(reply) => println!("{}", reply),
+			None => break,
+		}
+	}
+}
@@ -0,0 +1,45 @@
+use advent_of_code_2019::intcode::ascii::Player;
+use advent_of_code_2019::intcode::transcript::write_transcript;
+use advent_of_code_2019::intcode::{parse_program, Machine};
+use std::env;
+use std::fs::{read_to_string, File};
+use std::io::{self, BufRead, BufWriter, Write};
+
+const USAGE: &str = "usage: intcode-play [--record <transcript>] <program> [log]";
+
+pub fn main() {
+	let mut args: Vec<String> = env::args().skip(1).collect();
+	let record = match args.first().map(String::as_str) {
+		Some("--record") if args.len() > 1 => Some(args.drain(..2).nth(1).unwrap()),
+		_ => None,
+	};
+	let mut args = args.into_iter();
+	let path = args.next().expect(USAGE);
+	let code = parse_program(&read_to_string(path).unwrap()).unwrap();
+	let log: Box<dyn Write> = match args.next() {
+		Some(path) => Box::new(BufWriter::new(File::create(path).unwrap())),
+		None => Box::new(io::sink()),
+	};
+
+	let mut player = Player::new(Machine::new(code), log);
+	if record.is_some() {
+		player.ascii.transcript = Some(Vec::new());
+	}
+	print!("{}", player.start().unwrap());
+	let stdin = io::stdin();
+	while !player.stopped() {
+		print!("> ");
+		io::stdout().flush().unwrap();
+		let mut line = String::new();
+		if stdin.lock().read_line(&mut line).unwrap() == 0 {
+			break;
+		}
+		match player.execute(&line).unwrap() {
+			Some(reply) => print!("{}", reply),
+			None => break,
+		}
+	}
+	if let (Some(path), Some(events)) = (record, &player.ascii.transcript) {
+		write_transcript(BufWriter::new(File::create(path).unwrap()), events).unwrap();
+	}
+}
@@ -0,0 +1,22 @@
+use advent_of_code_2019::intcode::profiler::profile;
+use advent_of_code_2019::intcode::{parse_program, Machine};
+use std::env;
+use std::fs::read_to_string;
+
+pub fn main() {
+	let mut args: Vec<String> = env::args().skip(1).collect();
+	let csv = args.iter().any(|arg| arg == "--csv");
+	args.retain(|arg| arg != "--csv");
+	let path = args.first().expect("usage: intcode-prof [--csv] <program> [inputs...]");
+
+	let mut machine = Machine::new(parse_program(&read_to_string(path).unwrap()).unwrap());
+	machine.input = args[1..].iter().map(|n| n.parse().unwrap()).collect();
+	let profile = profile(&mut machine).unwrap();
+	if csv {
+		print!("{}", profile.csv());
+	}
+	else {
+		println!("output: {:?}\n", machine.output);
+		print!("{}", profile.table(10));
+	}
+}
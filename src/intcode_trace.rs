@@ -0,0 +1,45 @@
+use advent_of_code_2019::intcode::trace::{diff, read_trace, replay, TraceWriter};
+use advent_of_code_2019::intcode::{parse_program, ExitCode, Machine};
+use std::env;
+use std::fs::{read_to_string, File};
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+const USAGE: &str = "\
+usage: intcode-trace record <program> <trace> [inputs...]
+       intcode-trace replay <program> <trace>
+       intcode-trace diff <trace> <trace>";
+
+fn load(path: &str) -> Vec<i128> {
+	parse_program(&read_to_string(path).unwrap()).unwrap()
+}
+
+fn open(path: &str) -> BufReader<File> {
+	BufReader::new(File::open(path).unwrap())
+}
+
+pub fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let args: Vec<&str> = args.iter().map(String::as_str).collect();
+	let divergence = match args.as_slice() {
+		["record", program, trace, inputs @ ..] => {
+			let mut machine = Machine::new(load(program));
+			machine.input = inputs.iter().map(|n| n.parse().unwrap()).collect();
+			let mut writer = TraceWriter::new(BufWriter::new(File::create(trace).unwrap()));
+			while machine.run_traced(&mut writer).unwrap() == ExitCode::Print {}
+			writer.finish().unwrap();
+			println!("output: {:?}", machine.output);
+			None
+		},
+		["replay", program, trace] => replay(&mut Machine::new(load(program)), &read_trace(open(trace)).unwrap()),
+		["diff", a, b] => diff(&read_trace(open(a)).unwrap(), &read_trace(open(b)).unwrap()),
+		_ => {
+			eprintln!("{}", USAGE);
+			exit(2);
+		},
+	};
+	if let Some(divergence) = divergence {
+		println!("{}", divergence);
+		exit(1);
+	}
+}
@@ -0,0 +1,42 @@
+use advent_of_code_2019::intcode::transcript::{read_transcript, replay, write_transcript};
+use advent_of_code_2019::intcode::{parse_program, ExitCode, Machine};
+use std::env;
+use std::fs::{read_to_string, File};
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+const USAGE: &str = "\
+usage: intcode-transcript record <program> <transcript> [inputs...]
+       intcode-transcript replay <program> <transcript>";
+
+fn load(path: &str) -> Vec<i128> {
+	parse_program(&read_to_string(path).unwrap()).unwrap()
+}
+
+pub fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let args: Vec<&str> = args.iter().map(String::as_str).collect();
+	let divergence = match args.as_slice() {
+		["record", program, transcript, inputs @ ..] => {
+			let mut machine = Machine::new(load(program));
+			machine.input = inputs.iter().map(|n| n.parse().unwrap()).collect();
+			let mut events = Vec::new();
+			while machine.run_transcribed(&mut events).unwrap() == ExitCode::Print {}
+			write_transcript(BufWriter::new(File::create(transcript).unwrap()), &events).unwrap();
+			println!("output: {:?}", machine.output);
+			None
+		},
+		["replay", program, transcript] => {
+			let events = read_transcript(BufReader::new(File::open(transcript).unwrap())).unwrap();
+			replay(&mut Machine::new(load(program)), &events)
+		},
+		_ => {
+			eprintln!("{}", USAGE);
+			exit(2);
+		},
+	};
+	if let Some(divergence) = divergence {
+		println!("{}", divergence);
+		exit(1);
+	}
+}